@@ -23,7 +23,13 @@ pub fn get_info<S: Storage>(node: &Node<S>) -> AbciInfo {
         last_block_height: tendermint::block::Height::from(
             chain.get_height().revision_height as u32,
         ),
-        last_block_app_hash: vec![],
+        last_block_app_hash: chain
+            .get_block(0)
+            .expect("The chain should always contain a block")
+            .signed_header
+            .header
+            .app_hash
+            .value(),
     }
 }
 
@@ -31,7 +37,7 @@ pub fn get_info<S: Storage>(node: &Node<S>) -> AbciInfo {
 pub fn handle_query<S: Storage>(query: AbciQueryRequest, node: &Node<S>) -> AbciQuery {
     let loc = match query.height {
         None => Location::LatestStable,
-        Some(h) => Location::Stable(h.value()),
+        Some(h) => Location::Stable(node.chain().get_store_height(h.value())),
     };
     let store = node.store();
     let item = store.get(loc, &query.data);
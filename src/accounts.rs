@@ -0,0 +1,366 @@
+//! # Accounts
+//!
+//! This module mimics the Cosmos SDK `auth` module: it stores `BaseAccount`s, verifies the
+//! secp256k1 signatures of transactions over the SDK sign doc (`SIGN_MODE_DIRECT`) and increments
+//! the account sequences.
+//!
+//! Accounts are stored under `accounts/{address}`, where the address is the bech32 encoding of
+//! the RIPEMD-160 of the SHA-256 of the compressed public key. Accounts that are not part of the
+//! configuration are created the first time they sign a delivered transaction.
+use std::convert::{TryFrom, TryInto};
+
+use ibc_proto::cosmos::auth::v1beta1::BaseAccount;
+use ibc_proto::cosmos::tx::v1beta1::{AuthInfo, SignDoc, TxRaw};
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use prost::Message;
+use prost_types::Any;
+use ripemd160::Ripemd160;
+use sha2::{Digest, Sha256};
+
+use crate::logger::Log;
+use crate::node::{Counter, Node};
+use crate::store::{Location, Storage};
+use crate::tx::TxContext;
+
+pub const BASE_ACCOUNT_TYPE_URL: &str = "/cosmos.auth.v1beta1.BaseAccount";
+pub const SECP256K1_PUB_KEY_TYPE_URL: &str = "/cosmos.crypto.secp256k1.PubKey";
+const ACCOUNT_COUNTER_PATH: &str = "meta/accounts/counter";
+
+// Cosmos SDK error codes, in the `sdk` codespace.
+pub const CODE_UNAUTHORIZED: u32 = 4;
+pub const CODE_INVALID_PUB_KEY: u32 = 8;
+pub const CODE_NO_SIGNATURES: u32 = 15;
+pub const CODE_WRONG_SEQUENCE: u32 = 32;
+
+/// The `cosmos.crypto.secp256k1.PubKey` protobuf message.
+#[derive(Clone, PartialEq, Message)]
+pub struct Secp256k1PubKey {
+    #[prost(bytes, tag = "1")]
+    pub key: Vec<u8>,
+}
+
+/// An error raised while authenticating a transaction, holding an SDK error code and log.
+#[derive(Debug)]
+pub struct AuthError {
+    pub code: u32,
+    pub log: String,
+}
+
+impl AuthError {
+    fn new(code: u32, log: String) -> Self {
+        AuthError { code, log }
+    }
+}
+
+/// Returns the bech32 address of a compressed secp256k1 public key.
+pub fn address(pub_key: &[u8], prefix: &str) -> String {
+    let hash = Ripemd160::digest(&Sha256::digest(pub_key));
+    subtle_encoding::bech32::encode(prefix, hash.as_slice())
+}
+
+/// Returns the account at `address`, if any.
+pub fn get_account<S: Storage>(store: &S, loc: Location, address: &str) -> Option<BaseAccount> {
+    let path = format!("accounts/{}", address);
+    let value = store.get(loc, path.as_bytes())?;
+    BaseAccount::decode(value.as_slice()).ok()
+}
+
+/// Store an account, overwriting any previous value.
+pub fn set_account<S: Storage>(store: &S, account: &BaseAccount) {
+    let path = format!("accounts/{}", account.address);
+    let mut buffer = Vec::new();
+    account.encode(&mut buffer).unwrap();
+    store.set(path.into_bytes(), buffer);
+}
+
+/// Create a new account with the next available account number.
+pub fn new_account<S: Storage>(store: &S, address: String, pub_key: Option<Any>) -> BaseAccount {
+    let account_number = next_account_number(store);
+    store.set(
+        ACCOUNT_COUNTER_PATH.as_bytes().to_owned(),
+        Counter::from(account_number + 1).into(),
+    );
+    let account = BaseAccount {
+        address,
+        pub_key,
+        account_number,
+        sequence: 0,
+    };
+    set_account(store, &account);
+    log!(
+        Log::Store,
+        "New account {} (number {})",
+        account.address,
+        account_number
+    );
+    account
+}
+
+/// Returns the account number of the next account to be created.
+fn next_account_number<S: Storage>(store: &S) -> u64 {
+    match store.get(Location::Pending, ACCOUNT_COUNTER_PATH.as_bytes()) {
+        None => 0,
+        Some(counter_raw) => {
+            let counter: Counter = counter_raw.try_into().unwrap();
+            counter.into()
+        }
+    }
+}
+
+/// Verify the signatures and sequences of a transaction, charging gas and writing to the store
+/// through the context of the transaction.
+///
+/// Signatures are only checked if `verify_signatures` is true. If `increment_sequences` is true,
+/// the accounts of unknown signers are created and the sequence of each signer is incremented
+/// once all signers have been authenticated; otherwise the store is left untouched.
+pub fn authenticate<S: Storage>(
+    node: &Node<S>,
+    tx: &TxContext,
+    tx_raw: &TxRaw,
+    auth_info: &AuthInfo,
+    verify_signatures: bool,
+    increment_sequences: bool,
+) -> Result<(), AuthError> {
+    let signatures = &tx_raw.signatures;
+    if auth_info.signer_infos.is_empty() {
+        return Err(AuthError::new(
+            CODE_NO_SIGNATURES,
+            "no signatures supplied".to_owned(),
+        ));
+    }
+    if verify_signatures && signatures.len() != auth_info.signer_infos.len() {
+        return Err(AuthError::new(
+            CODE_UNAUTHORIZED,
+            format!(
+                "wrong number of signatures; expected {}, got {}: unauthorized",
+                auth_info.signer_infos.len(),
+                signatures.len()
+            ),
+        ));
+    }
+
+    let store = node.tx_store(tx);
+    let prefix = node.account_prefix().to_owned();
+    let chain_id = node.chain_id().to_string();
+    // The accounts of unknown signers are only created once the transaction is authenticated, in
+    // the meantime they are given the account numbers they will be created with.
+    let mut next_account_number = next_account_number(&store);
+    let mut accounts = Vec::with_capacity(signatures.len());
+    for (i, signer_info) in auth_info.signer_infos.iter().enumerate() {
+        let pub_key = signer_info
+            .public_key
+            .as_ref()
+            .ok_or_else(|| {
+                AuthError::new(
+                    CODE_INVALID_PUB_KEY,
+                    "pubkey on account is not set: invalid pubkey".to_owned(),
+                )
+            })
+            .and_then(decode_pub_key)?;
+        let address = address(&pub_key, &prefix);
+        let (mut account, is_new) = match get_account(&store, Location::Pending, &address) {
+            Some(account) => (account, false),
+            None => {
+                let account = BaseAccount {
+                    address,
+                    pub_key: signer_info.public_key.clone(),
+                    account_number: next_account_number,
+                    sequence: 0,
+                };
+                next_account_number += 1;
+                (account, true)
+            }
+        };
+
+        if signer_info.sequence != account.sequence {
+            return Err(AuthError::new(
+                CODE_WRONG_SEQUENCE,
+                format!(
+                    "account sequence mismatch, expected {}, got {}: incorrect account sequence",
+                    account.sequence, signer_info.sequence
+                ),
+            ));
+        }
+
+        let sign_doc = SignDoc {
+            body_bytes: tx_raw.body_bytes.clone(),
+            auth_info_bytes: tx_raw.auth_info_bytes.clone(),
+            chain_id: chain_id.clone(),
+            account_number: account.account_number,
+        };
+        let mut sign_bytes = Vec::new();
+        sign_doc.encode(&mut sign_bytes).unwrap();
+        tx.consume_gas(node.gas_config().sig_verify_cost_secp256k1, "sigVerify");
+        if verify_signatures && !verify_signature(&pub_key, &sign_bytes, &signatures[i]) {
+            return Err(AuthError::new(
+                CODE_UNAUTHORIZED,
+                format!(
+                    "signature verification failed; please verify account number ({}) and chain-id ({}): unauthorized",
+                    account.account_number, chain_id
+                ),
+            ));
+        }
+
+        if account.pub_key.is_none() {
+            account.pub_key = signer_info.public_key.clone();
+        }
+        accounts.push((account, is_new));
+    }
+
+    if increment_sequences {
+        for (mut account, is_new) in accounts {
+            if is_new {
+                account = new_account(&store, account.address, account.pub_key);
+            }
+            account.sequence += 1;
+            set_account(&store, &account);
+        }
+    }
+    Ok(())
+}
+
+/// Decode a public key from its protobuf `Any` representation.
+fn decode_pub_key(any: &Any) -> Result<Vec<u8>, AuthError> {
+    if any.type_url != SECP256K1_PUB_KEY_TYPE_URL {
+        return Err(AuthError::new(
+            CODE_INVALID_PUB_KEY,
+            format!(
+                "unsupported public key type {}: invalid pubkey",
+                any.type_url
+            ),
+        ));
+    }
+    Secp256k1PubKey::decode(any.value.as_slice())
+        .map(|pub_key| pub_key.key)
+        .map_err(|e| AuthError::new(CODE_INVALID_PUB_KEY, format!("{}: invalid pubkey", e)))
+}
+
+/// Verify a secp256k1 signature (64 bytes `r || s`) over the SHA-256 of `msg`.
+fn verify_signature(pub_key: &[u8], msg: &[u8], signature: &[u8]) -> bool {
+    let verifying_key = match VerifyingKey::from_sec1_bytes(pub_key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    match Signature::try_from(signature) {
+        Ok(signature) => verifying_key.verify(msg, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc_proto::cosmos::tx::v1beta1::SignerInfo;
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::SigningKey;
+
+    use super::*;
+    use crate::config::Config;
+
+    fn signer_info(pub_key: &[u8], sequence: u64) -> SignerInfo {
+        let mut value = Vec::new();
+        Secp256k1PubKey {
+            key: pub_key.to_vec(),
+        }
+        .encode(&mut value)
+        .unwrap();
+        SignerInfo {
+            public_key: Some(Any {
+                type_url: SECP256K1_PUB_KEY_TYPE_URL.to_owned(),
+                value,
+            }),
+            mode_info: None,
+            sequence,
+        }
+    }
+
+    /// Build a transaction signed by `key`.
+    fn signed_tx(
+        key: &SigningKey,
+        node: &Node<crate::store::Memory>,
+        sequence: u64,
+        account_number: u64,
+    ) -> (TxRaw, AuthInfo) {
+        let pub_key = key.verify_key().to_bytes();
+        let auth_info = AuthInfo {
+            signer_infos: vec![signer_info(&pub_key, sequence)],
+            fee: None,
+        };
+        let mut auth_info_bytes = Vec::new();
+        auth_info.encode(&mut auth_info_bytes).unwrap();
+        let sign_doc = SignDoc {
+            body_bytes: b"body".to_vec(),
+            auth_info_bytes: auth_info_bytes.clone(),
+            chain_id: node.chain_id().to_string(),
+            account_number,
+        };
+        let mut sign_bytes = Vec::new();
+        sign_doc.encode(&mut sign_bytes).unwrap();
+        let signature: Signature = key.sign(&sign_bytes);
+        let tx_raw = TxRaw {
+            body_bytes: b"body".to_vec(),
+            auth_info_bytes,
+            signatures: vec![signature.as_ref().to_vec()],
+        };
+        (tx_raw, auth_info)
+    }
+
+    #[test]
+    fn authenticate_and_increment_sequence() {
+        let node = Node::new(&Config::default());
+        let key = SigningKey::from_bytes(&[7; 32]).unwrap();
+
+        let tx = TxContext::new(u64::MAX);
+
+        for sequence in 0..3 {
+            let (tx_raw, auth_info) = signed_tx(&key, &node, sequence, 0);
+            authenticate(&node, &tx, &tx_raw, &auth_info, true, true).unwrap();
+        }
+
+        // The changes are buffered in the branch of the transaction
+        let address = address(&key.verify_key().to_bytes(), "cosmos");
+        assert!(get_account(&node.store(), Location::Pending, &address).is_none());
+        tx.branch().commit(node.chain().get_store());
+        let account = get_account(&node.store(), Location::Pending, &address).unwrap();
+        assert_eq!(account.sequence, 3);
+        assert_eq!(account.account_number, 0);
+
+        // Wrong sequence
+        let (tx_raw, auth_info) = signed_tx(&key, &node, 1, 0);
+        let err = authenticate(&node, &tx, &tx_raw, &auth_info, true, true).unwrap_err();
+        assert_eq!(err.code, CODE_WRONG_SEQUENCE);
+
+        // Wrong account number, only detected when verifying signatures
+        let (tx_raw, auth_info) = signed_tx(&key, &node, 3, 1);
+        let err = authenticate(&node, &tx, &tx_raw, &auth_info, true, false).unwrap_err();
+        assert_eq!(err.code, CODE_UNAUTHORIZED);
+        authenticate(&node, &tx, &tx_raw, &auth_info, false, false).unwrap();
+    }
+
+    #[test]
+    fn accounts_are_created_on_delivery() {
+        let node = Node::new(&Config::default());
+        let key = SigningKey::from_bytes(&[8; 32]).unwrap();
+        let address = address(&key.verify_key().to_bytes(), "cosmos");
+        let tx = TxContext::new(u64::MAX);
+
+        // Checking a transaction does not create the account of its signer
+        let (tx_raw, auth_info) = signed_tx(&key, &node, 0, 0);
+        authenticate(&node, &tx, &tx_raw, &auth_info, true, false).unwrap();
+        assert!(get_account(&node.tx_store(&tx), Location::Pending, &address).is_none());
+
+        // Neither does a transaction failing the signature verification
+        let (mut tx_raw, auth_info) = signed_tx(&key, &node, 0, 0);
+        tx_raw.signatures[0][0] ^= 1;
+        let err = authenticate(&node, &tx, &tx_raw, &auth_info, true, true).unwrap_err();
+        assert_eq!(err.code, CODE_UNAUTHORIZED);
+        assert!(get_account(&node.tx_store(&tx), Location::Pending, &address).is_none());
+
+        let (tx_raw, auth_info) = signed_tx(&key, &node, 0, 0);
+        authenticate(&node, &tx, &tx_raw, &auth_info, true, true).unwrap();
+        let account = get_account(&node.tx_store(&tx), Location::Pending, &address).unwrap();
+        assert_eq!(account.account_number, 0);
+        assert_eq!(account.sequence, 1);
+        assert_eq!(next_account_number(&node.tx_store(&tx)), 1);
+    }
+}
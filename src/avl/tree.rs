@@ -27,7 +27,6 @@ where
         AvlTree { root: None }
     }
 
-    #[allow(dead_code)]
     /// Return the hash of the merkle tree root, if it has at least one node.
     pub fn root_hash(&self) -> Option<&Hash> {
         Some(&self.root.as_ref()?.merkle_hash)
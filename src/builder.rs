@@ -6,29 +6,39 @@
 
 use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 use futures::future::try_join_all;
 use futures::try_join;
 
-use crate::config::Config;
+use crate::clock::Clock;
+use crate::config::{Config, Upgrade, Validator, ValidatorUpdate};
+use crate::growth::{schedule_growth, GrowthControl, GrowthHandle, GrowthSchedule};
 use crate::grpc;
 use crate::init;
 use crate::jrpc;
 use crate::logger::Log;
 use crate::node;
-use crate::store;
 
 /// Tendermock builder object.
 pub struct Tendermock {
-    /// Interval between new blocks, in seconds.
+    /// Intervals between new blocks.
     /// This parameter applies to all nodes in the Tendermock instance.
-    growth_interval: u64,
+    schedule: GrowthSchedule,
 
     /// A list of interfaces for the chain, the first address for JsonRPC and the second for gRPC.
     interfaces: Vec<(SocketAddr, SocketAddr)>,
 
     /// The genesis block configuration.
     config: Config,
+
+    /// The settings overriding the configuration, whether it is loaded before or after they are
+    /// set.
+    overrides: Overrides,
+
+    /// Controls the block production, shared with the growth handles.
+    growth: Arc<GrowthControl>,
 }
 
 impl Tendermock {
@@ -38,15 +48,49 @@ impl Tendermock {
     /// - Config: default
     pub fn new() -> Self {
         Tendermock {
-            growth_interval: 0,
+            schedule: GrowthSchedule::default(),
             interfaces: vec![],
             config: Config::default(),
+            overrides: Overrides::default(),
+            growth: Arc::new(GrowthControl::new()),
         }
     }
 
     /// Set the interval between two new blocks, in seconds.
     pub fn growth_rate(&mut self, interval: u64) -> &mut Self {
-        self.growth_interval = interval;
+        self.growth_interval(Duration::from_secs(interval))
+    }
+
+    /// Set the interval between two new blocks, a zero interval disables the automatic growth.
+    pub fn growth_interval(&mut self, interval: Duration) -> &mut Self {
+        self.schedule.interval = interval;
+        self
+    }
+
+    /// Randomly deviate the interval between two blocks by up to `jitter`, in both directions.
+    pub fn growth_jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.schedule.jitter = jitter;
+        self
+    }
+
+    /// Set the interval preceding the block at `height`, regardless of the base interval and
+    /// jitter.
+    pub fn block_interval_at(&mut self, height: u64, interval: Duration) -> &mut Self {
+        self.schedule.overrides.insert(height, interval);
+        self
+    }
+
+    /// Stop producing blocks automatically once the chain reaches `height`, the node keeps
+    /// serving requests.
+    pub fn halt_at(&mut self, height: u64) -> &mut Self {
+        self.schedule.halt_height = Some(height);
+        self
+    }
+
+    /// Stop producing blocks automatically for `duration` once the chain reaches `height`, the
+    /// node reports that it is catching up in the meantime.
+    pub fn stall_at(&mut self, height: u64, duration: Duration) -> &mut Self {
+        self.schedule.stalls.insert(height, duration);
         self
     }
 
@@ -62,15 +106,110 @@ impl Tendermock {
         self
     }
 
+    /// Set the clock used to timestamp the blocks, see `Clock`, overriding the configuration.
+    pub fn clock(&mut self, clock: Clock) -> &mut Self {
+        self.overrides.clock = Some(clock);
+        self
+    }
+
+    /// Replace the validator set starting at `height`, each validator is given by its id (from
+    /// which its key is derived) and its voting power.
+    ///
+    /// The update is added to the ones of the configuration.
+    pub fn schedule_validator_update(
+        &mut self,
+        height: u64,
+        validators: &[(&str, u64)],
+    ) -> &mut Self {
+        let validators = validators
+            .iter()
+            .map(|(id, voting_power)| Validator {
+                id: String::from(*id),
+                voting_power: *voting_power,
+            })
+            .collect();
+        self.overrides
+            .validator_updates
+            .push(ValidatorUpdate { height, validators });
+        self
+    }
+
+    /// Plan an upgrade of the chain: the block at `height` is the last one of the current
+    /// revision, the next revision starts from height 1 with the chain id `chain_id`.
+    ///
+    /// This overrides the upgrade of the configuration.
+    pub fn schedule_upgrade(&mut self, height: u64, chain_id: &str) -> &mut Self {
+        self.overrides.upgrade = Some(Upgrade {
+            height,
+            chain_id: Some(String::from(chain_id)),
+        });
+        self
+    }
+
+    /// Set the moniker of the node, a human readable name.
+    pub fn moniker(&mut self, moniker: &str) -> &mut Self {
+        self.overrides.moniker = Some(String::from(moniker));
+        self
+    }
+
+    /// Set the node id (20 hex encoded bytes), by default it is derived from the moniker and the
+    /// address of the first interface.
+    pub fn node_id(&mut self, id: &str) -> &mut Self {
+        self.overrides.node_id = Some(String::from(id));
+        self
+    }
+
+    /// Set the Tendermint version reported by the node.
+    pub fn node_version(&mut self, version: &str) -> &mut Self {
+        self.overrides.node_version = Some(String::from(version));
+        self
+    }
+
+    /// Returns the configuration of the node: the loaded configuration (or the default one) with
+    /// the settings of the builder on top.
+    fn config(&self) -> Config {
+        let mut config = self.config.clone();
+        let overrides = &self.overrides;
+        config
+            .validator_updates
+            .extend(overrides.validator_updates.iter().cloned());
+        if let Some(clock) = &overrides.clock {
+            config.clock = clock.clone();
+        }
+        if let Some(upgrade) = &overrides.upgrade {
+            config.upgrade = Some(upgrade.clone());
+        }
+        if let Some(moniker) = &overrides.moniker {
+            config.node_info.moniker = moniker.clone();
+        }
+        if let Some(id) = &overrides.node_id {
+            config.node_info.id = Some(id.clone());
+        }
+        if let Some(version) = &overrides.node_version {
+            config.node_info.version = version.clone();
+        }
+        config
+    }
+
+    /// Returns a handle to control the block production of the node once started.
+    pub fn handle(&self) -> GrowthHandle {
+        GrowthHandle::new(self.growth.clone())
+    }
+
     /// Start the Tendermock instance.
     ///
     /// This call is blocking, for running multiple nodes simultaneously threading can be used (a
     /// scheduler will run on each thread).
     pub fn start(&self) {
-        // Initialize a node.
-        let node = node::Node::new(&self.config);
+        // Initialize a node, which reports the address of its first interface
+        let config = self.config();
+        let node = match self.interfaces.first() {
+            Some((jrpc_addr, _)) => node::Node::new_at(&config, *jrpc_addr),
+            None => node::Node::new(&config),
+        };
+        let node = node.with_growth_control(self.growth.clone());
         let mut node = node.shared();
-        init::init(&mut node, &self.config);
+        init::init(&mut node, &config);
 
         // Build servers
         let mut jrpc_servers = Vec::new();
@@ -94,32 +233,50 @@ impl Tendermock {
                 try_join!(
                     try_join_all(jrpc_servers),
                     try_join_all(grpc_servers),
-                    schedule_growth(node, self.growth_interval)
+                    schedule_growth(node, self.schedule.clone())
                 )
             })
             .unwrap();
     }
 }
 
+/// The settings of the builder overriding the configuration.
+#[derive(Default)]
+struct Overrides {
+    validator_updates: Vec<ValidatorUpdate>,
+    clock: Option<Clock>,
+    upgrade: Option<Upgrade>,
+    moniker: Option<String>,
+    node_id: Option<String>,
+    node_version: Option<String>,
+}
+
 impl Default for Tendermock {
     fn default() -> Self {
         Self::new()
     }
 }
 
-/// Push a new block on the chain every `interval` seconds.
-async fn schedule_growth<S: store::Storage>(
-    node: node::SharedNode<S>,
-    interval: u64,
-) -> Result<(), std::convert::Infallible> {
-    node.grow();
-    if interval == 0 {
-        return Ok(());
-    }
-    loop {
-        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
-        let node_ref = node.write();
-        node_ref.chain().grow();
-        drop(node_ref);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let mut tendermock = Tendermock::new();
+        tendermock
+            .moniker("alice")
+            .schedule_validator_update(5, &[("bob", 10)])
+            .load_config("config/config.example.json")
+            .node_version("v0.34.9");
+
+        // The settings of the builder are kept, whether they are set before or after the
+        // configuration is loaded
+        let config = tendermock.config();
+        assert_eq!(config.chain_id, "chain_A");
+        assert_eq!(config.node_info.moniker, "alice");
+        assert_eq!(config.node_info.version, "v0.34.9");
+        assert_eq!(config.validator_updates.len(), 1);
+        assert_eq!(config.validator_updates[0].height, 5);
     }
 }
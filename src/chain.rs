@@ -2,48 +2,130 @@
 //!
 //! This modules defines the tendermock chain. The chain is a vector of light blocks, which are
 //! stripped down versions of 'real' tendermint blocks.
+//!
+//! Blocks are committed by the chain's validators: the commits carry valid signatures from the
+//! validators' keys, which are derived from their ids, so that the headers can be verified by a
+//! light client.
+//!
+//! The validator set can change over time following a schedule of validator updates: the
+//! validators of a block at height `h` are the `next_validators` of the block at height `h - 1`.
+//!
+//! The app hash of each header is the root hash of the store once the previous block is
+//! committed, the commits are signed once it is set.
+//!
+//! The chain can be upgraded at a planned height, after which a new revision starts from height 1
+//! with a new chain id (see the `upgrade` module). Only the blocks of the current revision can be
+//! retrieved. The store, the validator updates and the clock are indexed by absolute heights,
+//! which count the blocks of all revisions.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::sync::RwLock;
 
 use ibc::Height;
-use tendermint::Block as TmBlock;
+use sha2::{Digest, Sha256};
+use tendermint::abci::Transaction;
+use tendermint::block::CommitSig;
+use tendermint::signature::Signer;
+use tendermint::{vote, AppHash, Block as TmBlock, Hash};
 use tendermint_testgen::light_block::TmLightBlock;
-use tendermint_testgen::{Generator, LightBlock};
+use tendermint_testgen::{Commit, Generator, Header, LightBlock, Validator};
 
+use crate::clock::Clock;
 use crate::logger::Log;
-use crate::store::Storage;
+use crate::store::{Location, Storage};
+use crate::upgrade::{self, UpgradePlan};
 
 pub struct Chain<S: Storage> {
     blocks: RwLock<Blocks>,
     store: S,
+    /// The new validator sets, indexed by the height from which they sign the blocks.
+    validator_updates: BTreeMap<u64, Vec<Validator>>,
+    /// Whether the pending (not yet validated) block can be retrieved.
+    serve_pending_block: bool,
+    /// The source of the block timestamps.
+    clock: Clock,
 }
 
 struct Blocks {
     /// The chain of validated blocks.
-    chain: Vec<LightBlock>,
+    chain: Vec<Block>,
     /// The next block candidate, it will be considered valid once another block is added.
-    pending_block: LightBlock,
+    pending_block: Block,
+    /// The heights of the validated blocks, indexed by hash.
+    heights: HashMap<Hash, u64>,
+    /// The chain id of the current revision.
+    chain_id: String,
+    /// The revision number, incremented by each upgrade.
+    revision: u64,
+    /// The number of blocks of the previous revisions.
+    height_offset: u64,
+    /// The planned upgrade, if any.
+    upgrade: Option<UpgradePlan>,
+}
+
+impl Blocks {
+    /// Returns the pending block, unless it belongs to the next revision.
+    fn pending(&self) -> Option<&Block> {
+        if self.pending_block.height() == self.chain.len() as u64 + 1 {
+            Some(&self.pending_block)
+        } else {
+            None
+        }
+    }
+
+    /// Start a new revision, which begins with the pending block.
+    fn start_revision(&mut self) {
+        self.height_offset += self.chain.len() as u64;
+        self.revision += 1;
+        self.chain_id = self
+            .pending_block
+            .header()
+            .chain_id
+            .clone()
+            .expect("[Internal] Headers should have a chain id.");
+        self.chain.clear();
+        self.heights.clear();
+        self.upgrade = None;
+    }
 }
 
 impl<S: Storage> Chain<S> {
-    pub fn new(store: S) -> Self {
-        // To ease testing, the second block is always created at midnight. This fixes the hash of
-        // the header at height `2` for a given day (until next midnight).
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let midnight = now - (now % 86_400);
+    pub fn new(
+        store: S,
+        chain_id: &str,
+        validators: Vec<Validator>,
+        validator_updates: BTreeMap<u64, Vec<Validator>>,
+        serve_pending_block: bool,
+        clock: Clock,
+        upgrade: Option<UpgradePlan>,
+    ) -> Self {
         // Create genesis and pending block
-        let genesis = LightBlock::new_default(1);
-        let mut pending = genesis.next();
-        let mut header_ref = pending.header.as_mut().unwrap();
-        header_ref.time = Some(midnight);
+        let next_validators = validator_updates.get(&2).unwrap_or(&validators);
+        let genesis_header = Header::new(&validators)
+            .next_validators(next_validators)
+            .chain_id(chain_id)
+            .height(1)
+            .time(clock.time(1));
+        let pending_header = next_header(&genesis_header, 0, &clock, &validator_updates);
+        let app_hash = store.root_hash(Location::LatestStable).unwrap_or_default();
+        let genesis = Block::new(genesis_header, vec![]);
+        let pending = Block::new(pending_header, app_hash);
+        let mut heights = HashMap::new();
+        heights.insert(genesis.hash(), 1);
         Chain {
             blocks: RwLock::new(Blocks {
                 chain: vec![genesis],
                 pending_block: pending,
+                heights,
+                chain_id: String::from(chain_id),
+                revision: upgrade::revision_number(chain_id),
+                height_offset: 0,
+                upgrade,
             }),
             store,
+            validator_updates,
+            serve_pending_block,
+            clock,
         }
     }
 
@@ -56,42 +138,152 @@ impl<S: Storage> Chain<S> {
     ///
     /// The height is defined as the height of the latest validated blocks.
     pub fn get_height(&self) -> Height {
-        let blocks = &self.blocks.read().unwrap().chain;
+        let blocks = self.blocks.read().unwrap();
         let height = blocks
+            .chain
             .last()
             .expect("[Internal] Chain should be initialized with a block.")
             .height();
-        Height::new(1, height)
+        Height::new(blocks.revision, height)
+    }
+
+    /// Returns the absolute height of the chain, which counts the blocks of all revisions.
+    pub fn get_absolute_height(&self) -> u64 {
+        let blocks = self.blocks.read().unwrap();
+        blocks.height_offset + blocks.chain.len() as u64
+    }
+
+    /// Returns the store height corresponding to a height of the current revision.
+    pub fn get_store_height(&self, height: u64) -> u64 {
+        self.blocks.read().unwrap().height_offset + height
+    }
+
+    /// Returns the chain id of the current revision.
+    pub fn get_chain_id(&self) -> String {
+        self.blocks.read().unwrap().chain_id.clone()
+    }
+
+    /// Returns the planned upgrade, if it did not take place yet.
+    pub fn get_upgrade_plan(&self) -> Option<UpgradePlan> {
+        self.blocks.read().unwrap().upgrade.clone()
+    }
+
+    /// Returns the highest height at which a block can be retrieved, which is the height of the
+    /// pending block if it is served.
+    pub fn get_max_height(&self) -> u64 {
+        let blocks = self.blocks.read().unwrap();
+        let height = blocks.chain.len() as u64;
+        if self.serve_pending_block && blocks.pending().is_some() {
+            height + 1
+        } else {
+            height
+        }
+    }
+
+    /// Check that a block can be retrieved at `height`, where `None` means latest, and returns
+    /// the corresponding height. The errors are the ones of Tendermint.
+    pub fn resolve_height(&self, height: Option<u64>) -> Result<u64, String> {
+        match height {
+            None => Ok(self.get_height().revision_height),
+            Some(0) => Err(String::from("height must be greater than 0, but got 0")),
+            Some(height) => {
+                let max_height = self.get_max_height();
+                if height > max_height {
+                    Err(format!(
+                        "height {} must be less than or equal to the current blockchain height {}",
+                        height, max_height
+                    ))
+                } else {
+                    Ok(height)
+                }
+            }
+        }
     }
 
     /// Returns a Tendermint Light Block or None if no block exist at that height.
     pub fn get_block(&self, height: u64) -> Option<TmLightBlock> {
         let chain = &self.blocks.read().unwrap();
-        let block = Chain::<S>::get_block_at_height(height, &chain.chain, &chain.pending_block)?;
-        block.generate().ok()
+        let pending = if self.serve_pending_block {
+            chain.pending()
+        } else {
+            None
+        };
+        let block = Chain::<S>::get_block_at_height(height, &chain.chain, pending)?;
+        block.generate()
+    }
+
+    /// Returns the height of the validated block with the given hash, if any.
+    pub fn get_height_by_hash(&self, hash: &Hash) -> Option<u64> {
+        self.blocks.read().unwrap().heights.get(hash).copied()
+    }
+
+    /// Returns the validated Tendermint Light Block with the given hash, if any.
+    pub fn get_block_by_hash(&self, hash: &Hash) -> Option<TmLightBlock> {
+        let height = self.get_height_by_hash(hash)?;
+        self.get_block(height)
+    }
+
+    /// Returns a block conflicting with the validated block at `height`, or `None` if there is
+    /// no such block: it is signed by the same validators but has a different time and app hash,
+    /// and therefore a different hash.
+    ///
+    /// The app hash is `app_hash` if any, and otherwise the SHA-256 of the hash of the original
+    /// block. The conflicting block is not part of the chain, it can be used to forge evidences
+    /// of misbehaviour.
+    pub fn get_conflicting_block(
+        &self,
+        height: u64,
+        app_hash: Option<Vec<u8>>,
+    ) -> Option<TmLightBlock> {
+        let blocks = self.blocks.read().unwrap();
+        if height == 0 {
+            return None;
+        }
+        let block = blocks.chain.get((height - 1) as usize)?;
+        let app_hash = app_hash.unwrap_or_else(|| Sha256::digest(block.hash().as_bytes()).to_vec());
+        let header = block.header();
+        let time = header.time.unwrap_or(0) + 1;
+        Block::new(header.clone().time(time), app_hash).generate()
     }
 
     /// Grow the chain by adding a new block.
     pub fn grow(&self) {
-        // Date of the new block
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        // Create new block
+        // Commit the state of the pending block, which is the app hash of the next block
+        self.store.grow();
+        let app_hash = self
+            .store
+            .root_hash(Location::LatestStable)
+            .unwrap_or_default();
+
         let mut blocks = self.blocks.write().unwrap();
-        let mut next_block = blocks.pending_block.next();
-        let mut header_ref = next_block.header.as_mut().unwrap();
-        header_ref.time = Some(now);
+        if blocks.pending().is_none() {
+            blocks.start_revision();
+        }
+
+        // Create new block, which starts the next revision if the pending block is the last one
+        // of the current revision
+        let pending_header = blocks.pending_block.header();
+        let mut next_header = next_header(
+            pending_header,
+            blocks.height_offset,
+            &self.clock,
+            &self.validator_updates,
+        );
+        if let Some(plan) = &blocks.upgrade {
+            if blocks.pending_block.height() == plan.height {
+                next_header = next_header.chain_id(&plan.chain_id).height(1);
+            }
+        }
+        let hash = blocks.pending_block.hash();
+        let mut next_block = Block::new(next_header, app_hash);
 
         // Set next_block to pending and push the old pending to the chain
         std::mem::swap(&mut blocks.pending_block, &mut next_block);
+        let height = next_block.height();
         blocks.chain.push(next_block);
+        blocks.heights.insert(hash, height);
         drop(blocks); // Release lock
 
-        // Grow the store
-        self.store.grow();
-
         // Displays the last block of the node's chain.
         let block = self.get_block(0).unwrap();
         let header = block.signed_header.header;
@@ -106,26 +298,146 @@ impl<S: Storage> Chain<S> {
     /// Returns the store at a given height, where 0 means latest.
     fn get_block_at_height<'a>(
         height: u64,
-        blocks: &'a [LightBlock],
-        pending: &'a LightBlock,
-    ) -> Option<&'a LightBlock> {
+        blocks: &'a [Block],
+        pending: Option<&'a Block>,
+    ) -> Option<&'a Block> {
         if height == 0 {
             blocks.last()
         } else if height == (blocks.len() + 1) as u64 {
             // Preview of the next (not yet validated) block
-            Some(pending)
+            pending
         } else {
             blocks.get((height - 1) as usize)
         }
     }
 }
 
-/// Build a Tendermint block from a Tendermint light block.
-pub fn to_full_block(light_block: TmLightBlock) -> TmBlock {
+/// Build the header following `header`, its validators are the next validators of `header` and
+/// its next validators are taken from the updates, if any.
+///
+/// The `height_offset` is the number of blocks of the previous revisions.
+fn next_header(
+    header: &Header,
+    height_offset: u64,
+    clock: &Clock,
+    validator_updates: &BTreeMap<u64, Vec<Validator>>,
+) -> Header {
+    let height = height_offset
+        + header
+            .height
+            .expect("[Internal] Headers should have a height.")
+        + 1;
+    // Block times must be strictly increasing
+    let time = std::cmp::max(clock.time(height), header.time.unwrap_or(0) + 1);
+    let next = header.next().time(time);
+    match validator_updates.get(&(height + 1)) {
+        Some(validators) => next.next_validators(validators),
+        None => next,
+    }
+}
+
+/// A block of the chain: a testgen light block, along with the app hash of its header which
+/// testgen headers do not carry.
+struct Block {
+    light_block: LightBlock,
+    /// The root hash of the store once the previous block is committed, empty if the store is
+    /// empty.
+    app_hash: Vec<u8>,
+}
+
+impl Block {
+    /// Build a block with a commit signed by all the validators of the header.
+    fn new(header: Header, app_hash: Vec<u8>) -> Self {
+        let commit = Commit::new(header.clone(), 1);
+        Block {
+            light_block: LightBlock::new(header, commit),
+            app_hash,
+        }
+    }
+
+    fn header(&self) -> &Header {
+        self.light_block
+            .header
+            .as_ref()
+            .expect("[Internal] Blocks should have a header.")
+    }
+
+    fn height(&self) -> u64 {
+        self.light_block.height()
+    }
+
+    /// Returns the hash of the header, including its app hash.
+    fn hash(&self) -> Hash {
+        self.generate()
+            .expect("[Internal] Blocks should be valid.")
+            .signed_header
+            .header
+            .hash()
+    }
+
+    /// Generate the Tendermint light block, its commit is signed again once the app hash is set.
+    fn generate(&self) -> Option<TmLightBlock> {
+        let mut block = self.light_block.generate().ok()?;
+        if !self.app_hash.is_empty() {
+            block.signed_header.header.app_hash = AppHash::try_from(self.app_hash.clone()).ok()?;
+            sign_commit(&mut block, self.header());
+        }
+        Some(block)
+    }
+}
+
+/// Sign the commit of `block` again with the keys of the validators of `header`, the testgen
+/// header `block` was generated from, after its header was modified.
+fn sign_commit(block: &mut TmLightBlock, header: &Header) {
+    let validators = header
+        .validators
+        .as_ref()
+        .expect("[Internal] Headers should have validators.");
+    let signed_header = &mut block.signed_header;
+    let chain_id = signed_header.header.chain_id.clone();
+    let commit = &mut signed_header.commit;
+    commit.block_id.hash = signed_header.header.hash();
+    let (height, round, block_id) = (commit.height, commit.round, commit.block_id.clone());
+    for (index, commit_sig) in commit.signatures.iter_mut().enumerate() {
+        if let CommitSig::BlockIdFlagCommit {
+            validator_address,
+            timestamp,
+            signature,
+        } = commit_sig
+        {
+            let validator = validators
+                .iter()
+                .find(|validator| {
+                    validator.generate().map(|info| info.address).ok() == Some(*validator_address)
+                })
+                .expect("[Internal] Commits should be signed by the validators of the header.");
+            let vote = vote::Vote {
+                vote_type: vote::Type::Precommit,
+                height,
+                round,
+                block_id: Some(block_id.clone()),
+                timestamp: Some(*timestamp),
+                validator_address: *validator_address,
+                validator_index: vote::ValidatorIndex::try_from(index as u32).unwrap(),
+                signature: signature.clone(),
+            };
+            let sign_bytes = vote
+                .to_signable_vec(chain_id.clone())
+                .expect("[Internal] Votes should be encodable.");
+            let key = validator
+                .get_private_key()
+                .expect("[Internal] Validators should be valid.");
+            *signature = key.sign(&sign_bytes).into();
+        }
+    }
+}
+
+/// Build a Tendermint block containing `txs` from a Tendermint light block.
+pub fn to_full_block(light_block: TmLightBlock, txs: Vec<Transaction>) -> TmBlock {
     let signed_header = light_block.signed_header;
     let block = tendermint::Block::new(
         signed_header.header,
-        tendermint::abci::transaction::Data::default(), // TODO: should we include transaction data?
+        tendermint::abci::transaction::Data::new(txs),
         tendermint::evidence::Data::new(vec![]),
         Some(signed_header.commit),
     )
@@ -135,13 +447,45 @@ pub fn to_full_block(light_block: TmLightBlock) -> TmBlock {
 
 #[cfg(test)]
 mod test {
+    use std::time::Duration;
+
+    use tendermint_light_client::components::verifier::{ProdVerifier, Verdict, Verifier};
+    use tendermint_light_client::light_client::Options;
+    use tendermint_light_client::types::{LightBlock as LcLightBlock, TrustThreshold};
+
+    use crate::clock::ManualClock;
     use crate::store::Memory;
 
     use super::*;
 
+    fn new_chain() -> Chain<Memory> {
+        let validators = vec![
+            Validator::new("1").voting_power(50),
+            Validator::new("2").voting_power(50),
+        ];
+        Chain::new(
+            Memory::new(),
+            "tendermock",
+            validators,
+            BTreeMap::new(),
+            true,
+            Clock::Real,
+            None,
+        )
+    }
+
+    fn to_lc_block(block: TmLightBlock) -> LcLightBlock {
+        LcLightBlock::new(
+            block.signed_header,
+            block.validators,
+            block.next_validators,
+            block.provider,
+        )
+    }
+
     #[test]
     fn chain() {
-        let chain = Chain::new(Memory::new());
+        let chain = new_chain();
         let height = chain.get_height();
 
         // Chain is expected to start at height 1 (same as Storage)
@@ -155,4 +499,308 @@ mod test {
         let height = chain.get_height();
         assert_eq!(height.revision_height, 3); // Now the third block is valid
     }
+
+    #[test]
+    fn light_client_verification() {
+        let chain = new_chain();
+        chain.get_store().set(b"foo".to_vec(), b"bar".to_vec());
+        chain.grow();
+        chain.grow();
+        chain.grow();
+
+        // The headers carry the app hash of the store, the commits are signed accordingly
+        let app_hash = |height| {
+            chain
+                .get_block(height)
+                .unwrap()
+                .signed_header
+                .header
+                .app_hash
+        };
+        assert!(app_hash(2).value().is_empty());
+        assert!(!app_hash(3).value().is_empty());
+
+        let options = Options {
+            trust_threshold: TrustThreshold::default(),
+            trusting_period: Duration::from_secs(14 * 24 * 3600),
+            clock_drift: Duration::from_secs(60),
+        };
+        let verifier = ProdVerifier::default();
+        let now = tendermint::Time::now();
+
+        // Sequential verification
+        let trusted = to_lc_block(chain.get_block(2).unwrap());
+        let untrusted = to_lc_block(chain.get_block(3).unwrap());
+        assert_eq!(
+            untrusted.signed_header.header.chain_id.as_str(),
+            "tendermock"
+        );
+        let verdict = verifier.verify(&untrusted, &trusted, &options, now);
+        assert!(matches!(verdict, Verdict::Success), "{:?}", verdict);
+
+        // Skipping verification
+        let untrusted = to_lc_block(chain.get_block(4).unwrap());
+        let verdict = verifier.verify(&untrusted, &trusted, &options, now);
+        assert!(matches!(verdict, Verdict::Success), "{:?}", verdict);
+    }
+
+    #[test]
+    fn deterministic_clock() {
+        let new_fixed_chain = || {
+            let clock = Clock::Fixed {
+                start: 1_600_000_000,
+                step: 5,
+            };
+            let validators = vec![Validator::new("1").voting_power(50)];
+            Chain::new(
+                Memory::new(),
+                "tendermock",
+                validators,
+                BTreeMap::new(),
+                true,
+                clock,
+                None,
+            )
+        };
+        let chain_a = new_fixed_chain();
+        let chain_b = new_fixed_chain();
+        chain_a.grow();
+        chain_b.grow();
+        for height in 1..=3 {
+            let header_a = chain_a.get_block(height).unwrap().signed_header.header;
+            let header_b = chain_b.get_block(height).unwrap().signed_header.header;
+            assert_eq!(header_a.hash(), header_b.hash());
+            assert_eq!(
+                header_a.time,
+                tendermint::Time::unix_epoch()
+                    + Duration::from_secs(1_600_000_000 + (height - 1) * 5)
+            );
+        }
+
+        // A manual clock that is not advanced still produces increasing timestamps
+        let clock = ManualClock::new(1_600_000_000);
+        let validators = vec![Validator::new("1").voting_power(50)];
+        let chain = Chain::new(
+            Memory::new(),
+            "tendermock",
+            validators,
+            BTreeMap::new(),
+            true,
+            Clock::Manual(clock.clone()),
+            None,
+        );
+        chain.grow();
+        clock.advance(60);
+        chain.grow();
+        let time = |height| chain.get_block(height).unwrap().signed_header.header.time;
+        assert_eq!(time(2), time(1) + Duration::from_secs(1));
+        assert_eq!(time(3), time(1) + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn pending_block() {
+        let chain = new_chain();
+        assert_eq!(chain.resolve_height(None), Ok(1));
+        assert_eq!(chain.resolve_height(Some(2)), Ok(2));
+        assert_eq!(
+            chain.resolve_height(Some(3)),
+            Err(String::from(
+                "height 3 must be less than or equal to the current blockchain height 2"
+            ))
+        );
+        assert!(chain.resolve_height(Some(0)).is_err());
+
+        let chain = Chain::new(
+            Memory::new(),
+            "tendermock",
+            vec![Validator::new("1").voting_power(50)],
+            BTreeMap::new(),
+            false,
+            Clock::Real,
+            None,
+        );
+        assert!(chain.get_block(2).is_none());
+        assert_eq!(
+            chain.resolve_height(Some(2)),
+            Err(String::from(
+                "height 2 must be less than or equal to the current blockchain height 1"
+            ))
+        );
+        chain.grow();
+        assert!(chain.get_block(2).is_some());
+    }
+
+    #[test]
+    fn conflicting_block() {
+        let chain = new_chain();
+        chain.grow();
+        chain.grow();
+        assert!(chain.get_conflicting_block(0, None).is_none());
+        assert!(chain.get_conflicting_block(4, None).is_none());
+
+        let block = chain.get_block(3).unwrap();
+        let conflicting = chain.get_conflicting_block(3, None).unwrap();
+        let header = &block.signed_header.header;
+        let conflicting_header = &conflicting.signed_header.header;
+        assert_eq!(header.height, conflicting_header.height);
+        assert_eq!(header.validators_hash, conflicting_header.validators_hash);
+        assert_ne!(header.app_hash, conflicting_header.app_hash);
+        assert_ne!(header.hash(), conflicting_header.hash());
+
+        // The app hash can be chosen
+        let chosen = chain.get_conflicting_block(3, Some(vec![1, 2, 3])).unwrap();
+        assert_eq!(chosen.signed_header.header.app_hash.value(), vec![1, 2, 3]);
+
+        // Both blocks are valid according to a light client
+        let options = Options {
+            trust_threshold: TrustThreshold::default(),
+            trusting_period: Duration::from_secs(14 * 24 * 3600),
+            clock_drift: Duration::from_secs(60),
+        };
+        let verifier = ProdVerifier::default();
+        let now = tendermint::Time::now();
+        let trusted = to_lc_block(chain.get_block(2).unwrap());
+        for untrusted in &[block, conflicting, chosen] {
+            let untrusted = to_lc_block(untrusted.clone());
+            let verdict = verifier.verify(&untrusted, &trusted, &options, now);
+            assert!(matches!(verdict, Verdict::Success), "{:?}", verdict);
+        }
+    }
+
+    #[test]
+    fn block_by_hash() {
+        let chain = new_chain();
+        chain.grow();
+        chain.grow();
+
+        for height in 1..=3 {
+            let block = chain.get_block(height).unwrap();
+            let hash = block.signed_header.header.hash();
+            assert_eq!(chain.get_height_by_hash(&hash), Some(height));
+            let by_hash = chain.get_block_by_hash(&hash).unwrap();
+            assert_eq!(by_hash.signed_header.header.height.value(), height);
+        }
+
+        // The pending block is not indexed
+        let pending = chain.get_block(4).unwrap();
+        assert!(chain
+            .get_block_by_hash(&pending.signed_header.header.hash())
+            .is_none());
+    }
+
+    #[test]
+    fn validator_updates() {
+        let validators = vec![
+            Validator::new("1").voting_power(50),
+            Validator::new("2").voting_power(50),
+        ];
+        // The validator set is entirely replaced at height 4
+        let new_validators = vec![
+            Validator::new("3").voting_power(50),
+            Validator::new("4").voting_power(50),
+        ];
+        let mut updates = BTreeMap::new();
+        updates.insert(4, new_validators);
+        let chain = Chain::new(
+            Memory::new(),
+            "tendermock",
+            validators,
+            updates,
+            true,
+            Clock::Real,
+            None,
+        );
+        chain.grow();
+        chain.grow();
+        chain.grow();
+
+        let block_2 = chain.get_block(2).unwrap();
+        let block_3 = chain.get_block(3).unwrap();
+        let block_4 = chain.get_block(4).unwrap();
+        assert_eq!(
+            block_2.signed_header.header.next_validators_hash,
+            block_3.signed_header.header.validators_hash
+        );
+        assert_ne!(
+            block_3.signed_header.header.validators_hash,
+            block_3.signed_header.header.next_validators_hash
+        );
+        assert_eq!(
+            block_3.signed_header.header.next_validators_hash,
+            block_4.signed_header.header.validators_hash
+        );
+
+        let options = Options {
+            trust_threshold: TrustThreshold::default(),
+            trusting_period: Duration::from_secs(14 * 24 * 3600),
+            clock_drift: Duration::from_secs(60),
+        };
+        let verifier = ProdVerifier::default();
+        let now = tendermint::Time::now();
+
+        // Sequential verification follows the validator set changes
+        let verdict = verifier.verify(
+            &to_lc_block(block_4.clone()),
+            &to_lc_block(block_3),
+            &options,
+            now,
+        );
+        assert!(matches!(verdict, Verdict::Success), "{:?}", verdict);
+
+        // None of the trusted validators signed the new block
+        let verdict = verifier.verify(&to_lc_block(block_4), &to_lc_block(block_2), &options, now);
+        assert!(
+            matches!(verdict, Verdict::NotEnoughTrust(_)),
+            "{:?}",
+            verdict
+        );
+    }
+
+    #[test]
+    fn upgrade() {
+        let plan = UpgradePlan {
+            height: 3,
+            chain_id: String::from("chain-A-2"),
+        };
+        let chain = Chain::new(
+            Memory::new(),
+            "chain-A-1",
+            vec![Validator::new("1").voting_power(50)],
+            BTreeMap::new(),
+            true,
+            Clock::Real,
+            Some(plan),
+        );
+        assert_eq!(chain.get_height(), Height::new(1, 1));
+        chain.grow();
+        chain.grow();
+
+        // The next block belongs to the next revision and is not served yet
+        assert_eq!(chain.get_height(), Height::new(1, 3));
+        assert_eq!(chain.get_max_height(), 3);
+        assert!(chain.get_block(4).is_none());
+
+        chain.grow();
+        assert_eq!(chain.get_height(), Height::new(2, 1));
+        assert_eq!(chain.get_absolute_height(), 4);
+        assert_eq!(chain.get_store_height(1), 4);
+        assert_eq!(chain.get_chain_id(), "chain-A-2");
+        assert!(chain.get_upgrade_plan().is_none());
+        let header = chain.get_block(1).unwrap().signed_header.header;
+        assert_eq!(header.chain_id.as_str(), "chain-A-2");
+        assert_eq!(header.height.value(), 1);
+        assert_eq!(
+            chain
+                .get_block_by_hash(&header.hash())
+                .unwrap()
+                .signed_header
+                .header
+                .hash(),
+            header.hash()
+        );
+
+        chain.grow();
+        assert_eq!(chain.get_height(), Height::new(2, 2));
+        assert_eq!(chain.get_max_height(), 3);
+    }
 }
@@ -0,0 +1,76 @@
+//! # Clock
+//!
+//! This module defines the time source used to timestamp the blocks of the chain.
+//!
+//! By default blocks are timestamped with the system time, but a fixed or manual clock can be used
+//! instead to produce the same chain (and header hashes) across runs.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+/// A source of time for block timestamps, in seconds since the Unix epoch.
+///
+/// Block timestamps are always strictly increasing: a block is timestamped one second after its
+/// predecessor if the clock did not advance.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum Clock {
+    /// The system time.
+    Real,
+    /// The block at height `h` is timestamped `start + (h - 1) * step`.
+    Fixed { start: u64, step: u64 },
+    /// The time is advanced manually through a `ManualClock` handle.
+    #[serde(skip)]
+    Manual(ManualClock),
+}
+
+/// A clock whose time only changes when set or advanced, clones share the same time.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    time: Arc<AtomicU64>,
+}
+
+impl Clock {
+    /// Returns the time at which the block at `height` is produced.
+    pub fn time(&self, height: u64) -> u64 {
+        match self {
+            Clock::Real => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            Clock::Fixed { start, step } => start + height.saturating_sub(1) * step,
+            Clock::Manual(clock) => clock.now(),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::Real
+    }
+}
+
+impl ManualClock {
+    /// Create a manual clock starting at `start`, in seconds since the Unix epoch.
+    pub fn new(start: u64) -> Self {
+        ManualClock {
+            time: Arc::new(AtomicU64::new(start)),
+        }
+    }
+
+    /// Returns the current time of the clock.
+    pub fn now(&self) -> u64 {
+        self.time.load(Ordering::SeqCst)
+    }
+
+    /// Set the time of the clock.
+    pub fn set(&self, time: u64) {
+        self.time.store(time, Ordering::SeqCst);
+    }
+
+    /// Advance the clock by `seconds`.
+    pub fn advance(&self, seconds: u64) {
+        self.time.fetch_add(seconds, Ordering::SeqCst);
+    }
+}
@@ -7,25 +7,149 @@
 //! fields to the `Config` structs.
 //!
 //! The deserialization uses `serde` and `serde_json` to define and parse json config files.
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Deserialize)]
+use crate::clock::Clock;
+
+#[derive(Deserialize, Clone)]
 #[serde(deny_unknown_fields, default)]
 pub struct Config {
     pub chain_id: String,
     pub host_client: Client,
     pub clients: Vec<Client>,
     pub consensus_params: tendermint::consensus::Params,
+    pub gas: GasConfig,
+    /// The bech32 prefix of account addresses.
+    pub account_prefix: String,
+    /// The genesis accounts.
+    pub accounts: Vec<Account>,
+    /// The genesis validators, which sign the blocks of the chain.
+    #[serde(deserialize_with = "deserialize_validators")]
+    pub validators: Vec<Validator>,
+    /// Changes of the validator set after genesis.
+    #[serde(deserialize_with = "deserialize_validator_updates")]
+    pub validator_updates: Vec<ValidatorUpdate>,
+    /// Whether the pending block (the next block, not yet committed) is served by the RPC
+    /// interfaces.
+    pub serve_pending_block: bool,
+    /// The source of the block timestamps.
+    pub clock: Clock,
+    /// A planned upgrade of the chain, if any.
+    #[serde(deserialize_with = "deserialize_upgrade")]
+    pub upgrade: Option<Upgrade>,
+    /// The information reported by the node.
+    #[serde(deserialize_with = "deserialize_node_info")]
+    pub node_info: NodeInfo,
+    /// The settings of the websocket connections.
+    #[serde(deserialize_with = "deserialize_websocket")]
+    pub websocket: WebsocketConfig,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Client {
     pub id: String,
 }
 
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub address: String,
+}
+
+/// A validator, its signing key is derived from its id.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Validator {
+    pub id: String,
+    pub voting_power: u64,
+}
+
+/// A new validator set, which signs the blocks starting at `height`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ValidatorUpdate {
+    pub height: u64,
+    pub validators: Vec<Validator>,
+}
+
+/// A planned upgrade: the block at `height` is the last one of the current revision, the next
+/// revision uses `chain_id`, which defaults to the current chain id with an incremented revision
+/// number.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Upgrade {
+    pub height: u64,
+    #[serde(default)]
+    pub chain_id: Option<String>,
+}
+
+/// The information reported by the node, its addresses are the ones of its first interface.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub struct NodeInfo {
+    /// A human readable name for the node.
+    pub moniker: String,
+    /// The node id (20 hex encoded bytes), derived from the moniker and the address of the node
+    /// if omitted.
+    pub id: Option<String>,
+    /// The Tendermint version of the node.
+    pub version: String,
+    pub protocol_version: ProtocolVersion,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct ProtocolVersion {
+    pub p2p: u64,
+    pub block: u64,
+    pub app: u64,
+}
+
+/// The settings of the websocket connections, the default values are the ones of Tendermint.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub struct WebsocketConfig {
+    /// The interval between two pings sent to the client, in milliseconds.
+    pub ping_interval_ms: u64,
+    /// Clients are disconnected if no message (including pongs) is received for this long, in
+    /// milliseconds, it must be greater than the ping interval.
+    pub read_timeout_ms: u64,
+    /// The number of messages queued for a client, slow clients are disconnected once the queue
+    /// is full.
+    pub write_queue_capacity: usize,
+}
+
+/// The gas costs and fee requirements applied to transactions, the default values are the ones of
+/// the Cosmos SDK.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub struct GasConfig {
+    /// Flat cost of a store read.
+    pub read_cost_flat: u64,
+    /// Cost per byte read from the store.
+    pub read_cost_per_byte: u64,
+    /// Flat cost of a store write.
+    pub write_cost_flat: u64,
+    /// Cost per byte written to the store.
+    pub write_cost_per_byte: u64,
+    /// Cost of each value returned by a prefix query on the store.
+    pub iter_next_cost_flat: u64,
+    /// Cost of each message of a transaction.
+    pub msg_cost: u64,
+    /// Cost per byte of the raw transaction.
+    pub tx_size_cost_per_byte: u64,
+    /// Cost of the verification of a secp256k1 signature.
+    pub sig_verify_cost_secp256k1: u64,
+    /// The minimum price of a unit of gas, transactions paying less fees are rejected.
+    pub min_gas_price: f64,
+    /// The denomination in which fees are paid.
+    pub fee_denom: String,
+}
+
 impl Config {
     pub fn load<P: AsRef<Path>>(path: P) -> Self {
         let config = fs::read_to_string(&path)
@@ -34,6 +158,112 @@ impl Config {
     }
 }
 
+impl WebsocketConfig {
+    /// Checks that the settings can be applied, the pings must be sent before the read timeout.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ping_interval_ms == 0 || self.ping_interval_ms >= self.read_timeout_ms {
+            return Err(String::from(
+                "the websocket ping interval must be shorter than the read timeout",
+            ));
+        }
+        if self.write_queue_capacity == 0 {
+            return Err(String::from("the websocket write queue can not be empty"));
+        }
+        Ok(())
+    }
+}
+
+impl ValidatorUpdate {
+    /// Checks that the update takes place after genesis and keeps at least one validator.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.height <= 1 || self.validators.is_empty() {
+            return Err(String::from(
+                "validator updates must take place after genesis and contain at least one validator",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Upgrade {
+    /// Checks that the upgrade takes place after the second block.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.height <= 2 {
+            return Err(String::from(
+                "upgrades must take place after the second block",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl NodeInfo {
+    /// Checks that the node id and version can be reported by the node.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(id) = &self.id {
+            tendermint::node::Id::from_str(id).map_err(|_| format!("invalid node id: {}", id))?;
+        }
+        serde_json::from_value::<tendermint::Version>(serde_json::Value::String(
+            self.version.clone(),
+        ))
+        .map_err(|_| format!("invalid node version: {}", self.version))?;
+        Ok(())
+    }
+}
+
+/// Deserializes a value and checks it with `validate`, invalid values are reported as parsing
+/// errors.
+fn deserialize_valid<'de, D, T, F>(deserializer: D, validate: F) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+    F: Fn(&T) -> Result<(), String>,
+{
+    let value = T::deserialize(deserializer)?;
+    validate(&value).map_err(serde::de::Error::custom)?;
+    Ok(value)
+}
+
+fn deserialize_validators<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Validator>, D::Error> {
+    deserialize_valid(deserializer, |validators: &Vec<Validator>| {
+        if validators.is_empty() {
+            Err(String::from(
+                "the configuration must contain at least one validator",
+            ))
+        } else {
+            Ok(())
+        }
+    })
+}
+
+fn deserialize_validator_updates<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ValidatorUpdate>, D::Error> {
+    deserialize_valid(deserializer, |updates: &Vec<ValidatorUpdate>| {
+        updates.iter().try_for_each(ValidatorUpdate::validate)
+    })
+}
+
+fn deserialize_upgrade<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Upgrade>, D::Error> {
+    deserialize_valid(deserializer, |upgrade: &Option<Upgrade>| {
+        upgrade.as_ref().map_or(Ok(()), Upgrade::validate)
+    })
+}
+
+fn deserialize_node_info<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NodeInfo, D::Error> {
+    deserialize_valid(deserializer, NodeInfo::validate)
+}
+
+fn deserialize_websocket<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<WebsocketConfig, D::Error> {
+    deserialize_valid(deserializer, WebsocketConfig::validate)
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -43,6 +273,63 @@ impl Default for Config {
             },
             clients: vec![],
             consensus_params: default_params(),
+            gas: GasConfig::default(),
+            account_prefix: String::from("cosmos"),
+            accounts: vec![],
+            validators: vec![
+                Validator {
+                    id: String::from("1"),
+                    voting_power: 50,
+                },
+                Validator {
+                    id: String::from("2"),
+                    voting_power: 50,
+                },
+            ],
+            validator_updates: vec![],
+            serve_pending_block: true,
+            clock: Clock::default(),
+            upgrade: None,
+            node_info: NodeInfo::default(),
+            websocket: WebsocketConfig::default(),
+        }
+    }
+}
+
+impl Default for NodeInfo {
+    fn default() -> Self {
+        NodeInfo {
+            moniker: String::from("tendermock"),
+            id: None,
+            version: String::from("v0.1.0"),
+            protocol_version: ProtocolVersion::default(),
+        }
+    }
+}
+
+impl Default for WebsocketConfig {
+    fn default() -> Self {
+        WebsocketConfig {
+            ping_interval_ms: 27_000,
+            read_timeout_ms: 30_000,
+            write_queue_capacity: 100,
+        }
+    }
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        GasConfig {
+            read_cost_flat: 1000,
+            read_cost_per_byte: 3,
+            write_cost_flat: 2000,
+            write_cost_per_byte: 30,
+            iter_next_cost_flat: 30,
+            msg_cost: 1000,
+            tx_size_cost_per_byte: 10,
+            sig_verify_cost_secp256k1: 1000,
+            min_gas_price: 0.0,
+            fee_denom: String::from("stake"),
         }
     }
 }
@@ -64,3 +351,69 @@ fn default_params() -> tendermint::consensus::Params {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn websocket() {
+        let parse = |websocket: &str| {
+            serde_json::from_str::<Config>(&format!("{{ \"websocket\": {} }}", websocket))
+                .map(|config| config.websocket)
+                .map_err(|e| e.to_string())
+        };
+        let config = parse("{ \"ping_interval_ms\": 1000 }").unwrap();
+        assert_eq!(config.read_timeout_ms, 30_000);
+
+        let error = parse("{ \"ping_interval_ms\": 30000 }").unwrap_err();
+        assert!(error.contains("ping interval must be shorter than the read timeout"));
+        assert!(parse("{ \"ping_interval_ms\": 0 }").is_err());
+        let error = parse("{ \"write_queue_capacity\": 0 }").unwrap_err();
+        assert!(error.contains("write queue can not be empty"));
+    }
+
+    #[test]
+    fn chain() {
+        let parse = |config: &str| {
+            serde_json::from_str::<Config>(config)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        };
+        let validator = "{ \"id\": \"1\", \"voting_power\": 10 }";
+        assert!(parse(&format!(
+            "{{ \"validator_updates\": [{{ \"height\": 2, \"validators\": [{}] }}], \"upgrade\": {{ \"height\": 3 }} }}",
+            validator
+        ))
+        .is_ok());
+
+        let error = parse("{ \"validators\": [] }").unwrap_err();
+        assert!(error.contains("at least one validator"));
+        let error = parse(&format!(
+            "{{ \"validator_updates\": [{{ \"height\": 1, \"validators\": [{}] }}] }}",
+            validator
+        ))
+        .unwrap_err();
+        assert!(error.contains("validator updates must take place after genesis"));
+        assert!(
+            parse("{ \"validator_updates\": [{ \"height\": 2, \"validators\": [] }] }").is_err()
+        );
+        let error = parse("{ \"upgrade\": { \"height\": 2 } }").unwrap_err();
+        assert!(error.contains("upgrades must take place after the second block"));
+    }
+
+    #[test]
+    fn node_info() {
+        let parse = |node_info: &str| {
+            serde_json::from_str::<Config>(&format!("{{ \"node_info\": {} }}", node_info))
+                .map(|config| config.node_info)
+                .map_err(|e| e.to_string())
+        };
+        let id = "a".repeat(40);
+        let config = parse(&format!("{{ \"id\": \"{}\" }}", id)).unwrap();
+        assert_eq!(config.id, Some(id));
+
+        let error = parse("{ \"id\": \"not an id\" }").unwrap_err();
+        assert!(error.contains("invalid node id: not an id"));
+    }
+}
@@ -0,0 +1,213 @@
+//! # Events
+//!
+//! This module defines the event bus of the node, which publishes the Tendermint events
+//! (`NewBlock`, `NewBlockHeader` and `Tx`) to its subscribers, such as websocket clients.
+//!
+//! Each event carries its attributes indexed by composite keys (`{event type}.{attribute key}`),
+//! including `tm.event` for the kind of event, which subscribers filter with Tendermint queries
+//! (see the `query` module).
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tendermint::abci::transaction::Hash;
+use tendermint::abci::Transaction;
+use tendermint::block::{self, Header};
+use tendermint::Block;
+use tendermint_rpc::endpoint::broadcast::tx_commit::TxResult;
+use tokio::sync::broadcast;
+
+mod query;
+
+pub use query::Query;
+
+/// The number of events kept for the subscribers lagging behind.
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// A bus publishing the events of a node to its subscribers.
+#[derive(Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<Event>>,
+}
+
+/// An event, as published on the bus.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub data: EventData,
+    /// The attributes of the event, indexed by composite keys.
+    pub attributes: BTreeMap<String, Vec<String>>,
+}
+
+/// The content of an event, in the format of Tendermint.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", content = "value")]
+pub enum EventData {
+    #[serde(rename = "tendermint/event/NewBlock")]
+    NewBlock {
+        block: Box<Block>,
+        result_begin_block: EmptyResult,
+        result_end_block: EmptyResult,
+    },
+    #[serde(rename = "tendermint/event/NewBlockHeader")]
+    NewBlockHeader {
+        header: Box<Header>,
+        num_txs: String,
+        result_begin_block: EmptyResult,
+        result_end_block: EmptyResult,
+    },
+    #[serde(rename = "tendermint/event/Tx")]
+    Tx {
+        #[serde(rename = "TxResult")]
+        tx_result: TxInfo,
+    },
+}
+
+/// The result of the `BeginBlock` and `EndBlock` ABCI calls, which are always empty.
+#[derive(Serialize, Clone, Debug)]
+pub struct EmptyResult {}
+
+/// A transaction and the result of its delivery.
+#[derive(Serialize, Clone, Debug)]
+pub struct TxInfo {
+    pub height: block::Height,
+    pub index: u32,
+    pub tx: Transaction,
+    pub result: TxResult,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        EventBus { sender }
+    }
+
+    /// Publish an event to the current subscribers.
+    pub fn publish(&self, event: Event) {
+        // Publishing fails if there is no subscriber, in which case the event is dropped
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    /// Subscribe to the events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new()
+    }
+}
+
+impl Event {
+    /// A `NewBlock` event, published when a block is committed.
+    pub fn new_block(block: Block) -> Self {
+        Event {
+            data: EventData::NewBlock {
+                block: Box::new(block),
+                result_begin_block: EmptyResult {},
+                result_end_block: EmptyResult {},
+            },
+            attributes: event_type("NewBlock"),
+        }
+    }
+
+    /// A `NewBlockHeader` event, published when a block is committed.
+    pub fn new_block_header(header: Header, num_txs: usize) -> Self {
+        Event {
+            data: EventData::NewBlockHeader {
+                header: Box::new(header),
+                num_txs: num_txs.to_string(),
+                result_begin_block: EmptyResult {},
+                result_end_block: EmptyResult {},
+            },
+            attributes: event_type("NewBlockHeader"),
+        }
+    }
+
+    /// A `Tx` event, published when a transaction is included in a block. The ABCI events of the
+    /// transaction are indexed as attributes.
+    pub fn tx(hash: Hash, tx: TxInfo) -> Self {
+        let mut attributes = event_type("Tx");
+        attributes.insert(String::from("tx.hash"), vec![hash.to_string()]);
+        attributes.insert(String::from("tx.height"), vec![tx.height.to_string()]);
+        for event in &tx.result.events {
+            for tag in &event.attributes {
+                attributes
+                    .entry(format!("{}.{}", event.type_str, tag.key))
+                    .or_insert_with(Vec::new)
+                    .push(tag.value.to_string());
+            }
+        }
+        Event {
+            data: EventData::Tx { tx_result: tx },
+            attributes,
+        }
+    }
+}
+
+fn event_type(event_type: &str) -> BTreeMap<String, Vec<String>> {
+    let mut attributes = BTreeMap::new();
+    attributes.insert(String::from("tm.event"), vec![String::from(event_type)]);
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::node::Node;
+
+    #[test]
+    fn bus() {
+        let node = Node::new(&Config::default());
+        let mut subscriber = node.events().subscribe();
+        node.grow();
+
+        let event = subscriber.try_recv().unwrap();
+        assert!(matches!(event.data, EventData::NewBlock { .. }));
+        let event = subscriber.try_recv().unwrap();
+        assert!(matches!(event.data, EventData::NewBlockHeader { .. }));
+        let query: Query = "tm.event='NewBlockHeader'".parse().unwrap();
+        assert!(query.matches(&event.attributes));
+        assert!(subscriber.try_recv().is_err());
+
+        let json = serde_json::to_value(&event.data).unwrap();
+        assert_eq!(json["type"], "tendermint/event/NewBlockHeader");
+        assert_eq!(json["value"]["header"]["height"], "2");
+        assert_eq!(json["value"]["num_txs"], "0");
+    }
+
+    #[test]
+    fn block_txs() {
+        let mut node = Node::new(&Config::default());
+        let mut subscriber = node.events().subscribe();
+        let hash = Hash::new([1; 32]);
+        let tx = Transaction::new(vec![1, 2, 3]);
+        assert!(node.grow_with_txs(vec![(hash, tx.clone(), crate::tx::empty_result())]));
+
+        // The transaction is part of the block, and indexed once its events are published
+        let event = subscriber.try_recv().unwrap();
+        match &event.data {
+            EventData::NewBlock { block, .. } => {
+                assert_eq!(block.data.iter().collect::<Vec<_>>(), vec![&tx])
+            }
+            _ => panic!("expected a NewBlock event"),
+        }
+        assert_eq!(node.get_tx(&hash).unwrap().tx, tx);
+        let event = subscriber.try_recv().unwrap();
+        let json = serde_json::to_value(&event.data).unwrap();
+        assert_eq!(json["value"]["num_txs"], "1");
+        let event = subscriber.try_recv().unwrap();
+        let query: Query = format!("tm.event='Tx' AND tx.hash='{}'", hash)
+            .parse()
+            .unwrap();
+        assert!(query.matches(&event.attributes));
+        assert!(subscriber.try_recv().is_err());
+        let block = node.chain().get_block(0).unwrap();
+        assert_eq!(
+            node.get_block_txs(block.signed_header.header.height),
+            vec![tx]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! # Queries
+//!
+//! A parser for the Tendermint query language, used to subscribe to events, such as
+//! `tm.event='Tx' AND send_packet.packet_src_channel='channel-0'`.
+//!
+//! A query is a conjunction of conditions on the attributes of the events, which are indexed by
+//! composite keys (`{event type}.{attribute key}`). The supported operations are `=`, `<`, `<=`,
+//! `>`, `>=`, `CONTAINS` and `EXISTS`, and the operands are strings (`'...'`), numbers, times
+//! (`TIME 2021-01-01T00:00:00Z`) and dates (`DATE 2021-01-01`).
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// A parsed query, events match the query if they satisfy all of its conditions.
+#[derive(Clone, Debug)]
+pub struct Query {
+    source: String,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Condition {
+    key: String,
+    operation: Operation,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operation {
+    Eq(Operand),
+    Lt(Operand),
+    Le(Operand),
+    Gt(Operand),
+    Ge(Operand),
+    Contains(String),
+    Exists,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    String(String),
+    Number(f64),
+    Time(DateTime<Utc>),
+    Date(NaiveDate),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Query {
+    /// Returns the query as written by the subscriber.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the attributes of an event, indexed by composite keys, satisfy the query.
+    pub fn matches(&self, attributes: &BTreeMap<String, Vec<String>>) -> bool {
+        self.conditions.iter().all(|condition| {
+            match (attributes.get(&condition.key), &condition.operation) {
+                (Some(_), Operation::Exists) => true,
+                (Some(values), operation) => values.iter().any(|value| operation.matches(value)),
+                (None, _) => false,
+            }
+        })
+    }
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(source)?.into_iter();
+        let mut conditions = vec![parse_condition(&mut tokens)?];
+        while let Some(token) = tokens.next() {
+            if token != Token::Word(String::from("AND")) {
+                return Err(format!("expected AND, got {:?}", token));
+            }
+            conditions.push(parse_condition(&mut tokens)?);
+        }
+        Ok(Query {
+            source: String::from(source),
+            conditions,
+        })
+    }
+}
+
+impl Operation {
+    /// Whether a single attribute value satisfies the operation.
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Operation::Eq(operand) => operand.compare(value) == Some(Ordering::Equal),
+            Operation::Lt(operand) => operand.compare(value) == Some(Ordering::Less),
+            Operation::Le(operand) => matches!(
+                operand.compare(value),
+                Some(Ordering::Less) | Some(Ordering::Equal)
+            ),
+            Operation::Gt(operand) => operand.compare(value) == Some(Ordering::Greater),
+            Operation::Ge(operand) => matches!(
+                operand.compare(value),
+                Some(Ordering::Greater) | Some(Ordering::Equal)
+            ),
+            Operation::Contains(operand) => value.contains(operand.as_str()),
+            Operation::Exists => true,
+        }
+    }
+}
+
+impl Operand {
+    /// Compare an attribute value to the operand, returns `None` if the value can not be
+    /// interpreted as the type of the operand.
+    fn compare(&self, value: &str) -> Option<Ordering> {
+        match self {
+            Operand::String(operand) => Some(value.cmp(operand.as_str())),
+            Operand::Number(operand) => value.parse::<f64>().ok()?.partial_cmp(operand),
+            Operand::Time(operand) => Some(
+                DateTime::parse_from_rfc3339(value)
+                    .ok()?
+                    .with_timezone(&Utc)
+                    .cmp(operand),
+            ),
+            Operand::Date(operand) => Some(parse_date(value)?.cmp(operand)),
+        }
+    }
+}
+
+fn parse_condition<I: Iterator<Item = Token>>(tokens: &mut I) -> Result<Condition, String> {
+    let key = match tokens.next() {
+        Some(Token::Word(key)) => key,
+        token => return Err(format!("expected a key, got {:?}", token)),
+    };
+    let operation = match tokens.next() {
+        Some(Token::Word(word)) if word == "EXISTS" => Operation::Exists,
+        Some(Token::Word(word)) if word == "CONTAINS" => match tokens.next() {
+            Some(Token::Quoted(operand)) => Operation::Contains(operand),
+            token => return Err(format!("expected a string, got {:?}", token)),
+        },
+        Some(Token::Eq) => Operation::Eq(parse_operand(tokens)?),
+        Some(Token::Lt) => Operation::Lt(parse_operand(tokens)?),
+        Some(Token::Le) => Operation::Le(parse_operand(tokens)?),
+        Some(Token::Gt) => Operation::Gt(parse_operand(tokens)?),
+        Some(Token::Ge) => Operation::Ge(parse_operand(tokens)?),
+        token => return Err(format!("expected an operator, got {:?}", token)),
+    };
+    Ok(Condition { key, operation })
+}
+
+fn parse_operand<I: Iterator<Item = Token>>(tokens: &mut I) -> Result<Operand, String> {
+    match tokens.next() {
+        Some(Token::Quoted(operand)) => Ok(Operand::String(operand)),
+        Some(Token::Word(word)) if word == "TIME" => match tokens.next() {
+            Some(Token::Word(time)) => DateTime::parse_from_rfc3339(&time)
+                .map(|time| Operand::Time(time.with_timezone(&Utc)))
+                .map_err(|_| format!("invalid time: {}", time)),
+            token => Err(format!("expected a time, got {:?}", token)),
+        },
+        Some(Token::Word(word)) if word == "DATE" => match tokens.next() {
+            Some(Token::Word(date)) => parse_date(&date)
+                .map(Operand::Date)
+                .ok_or_else(|| format!("invalid date: {}", date)),
+            token => Err(format!("expected a date, got {:?}", token)),
+        },
+        Some(Token::Word(number)) => number
+            .parse()
+            .map(Operand::Number)
+            .map_err(|_| format!("invalid number: {}", number)),
+        token => Err(format!("expected an operand, got {:?}", token)),
+    }
+}
+
+/// Parse a date, or the date of a time.
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(date)
+                .ok()
+                .map(|time| time.naive_utc().date())
+        })
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '\'' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => quoted.push(c),
+                        None => return Err(String::from("unterminated string")),
+                    }
+                }
+                tokens.push(Token::Quoted(quoted));
+            }
+            '=' => tokens.push(Token::Eq),
+            '<' | '>' => {
+                let or_equal = chars.peek() == Some(&'=');
+                if or_equal {
+                    chars.next();
+                }
+                tokens.push(match (c, or_equal) {
+                    ('<', false) => Token::Lt,
+                    ('<', true) => Token::Le,
+                    ('>', false) => Token::Gt,
+                    _ => Token::Ge,
+                });
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !is_word_char(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => return Err(format!("unexpected character: '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "._-:+/".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(pairs: &[(&str, &str)]) -> BTreeMap<String, Vec<String>> {
+        let mut attributes = BTreeMap::new();
+        for (key, value) in pairs {
+            attributes
+                .entry(String::from(*key))
+                .or_insert_with(Vec::new)
+                .push(String::from(*value));
+        }
+        attributes
+    }
+
+    #[test]
+    fn parse() {
+        let query: Query = "tm.event='Tx' AND send_packet.packet_src_channel='channel-0'"
+            .parse()
+            .unwrap();
+        assert_eq!(query.conditions.len(), 2);
+        assert_eq!(
+            query.conditions[1],
+            Condition {
+                key: String::from("send_packet.packet_src_channel"),
+                operation: Operation::Eq(Operand::String(String::from("channel-0"))),
+            }
+        );
+
+        let query: Query = "tx.height>=5 AND transfer.sender EXISTS AND block.time < TIME \
+            2021-01-01T00:00:00Z AND message.action CONTAINS 'packet'"
+            .parse()
+            .unwrap();
+        assert_eq!(query.conditions.len(), 4);
+        assert_eq!(
+            query.conditions[0].operation,
+            Operation::Ge(Operand::Number(5.0))
+        );
+
+        assert!("".parse::<Query>().is_err());
+        assert!("tm.event=".parse::<Query>().is_err());
+        assert!("tm.event='Tx' OR tm.event='NewBlock'"
+            .parse::<Query>()
+            .is_err());
+        assert!("tm.event='Tx".parse::<Query>().is_err());
+        assert!("tx.height > five".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let tx = attributes(&[
+            ("tm.event", "Tx"),
+            ("tx.height", "12"),
+            ("send_packet.packet_src_channel", "channel-1"),
+            ("send_packet.packet_src_channel", "channel-0"),
+            ("message.time", "2020-06-01T12:00:00Z"),
+        ]);
+        let matches = |query: &str| query.parse::<Query>().unwrap().matches(&tx);
+
+        assert!(matches("tm.event='Tx'"));
+        assert!(!matches("tm.event='NewBlock'"));
+        assert!(matches(
+            "tm.event='Tx' AND send_packet.packet_src_channel='channel-0'"
+        ));
+        assert!(!matches(
+            "tm.event='Tx' AND send_packet.packet_src_channel='channel-2'"
+        ));
+        assert!(matches("tx.height > 10 AND tx.height <= 12"));
+        assert!(!matches("tx.height < 12"));
+        assert!(matches("send_packet.packet_src_channel CONTAINS 'channel'"));
+        assert!(matches("send_packet.packet_src_channel EXISTS"));
+        assert!(!matches("recv_packet.packet_dst_channel EXISTS"));
+        assert!(matches("message.time < TIME 2021-01-01T00:00:00Z"));
+        assert!(matches("message.time >= DATE 2020-06-01"));
+    }
+}
@@ -0,0 +1,171 @@
+//! # Gas
+//!
+//! This module implements a gas meter modeled after the Cosmos SDK one. While a transaction is
+//! being executed, gas is charged to its own meter for each access to the store (through the
+//! `MeteredStore` wrapper) and for each message, according to the costs of the `gas` section of
+//! the configuration.
+use std::sync::Mutex;
+
+use crate::config::GasConfig;
+use crate::store::{Branch, Location, PathValue, Storage};
+
+/// Tracks the gas consumed by a transaction against its gas limit.
+#[derive(Debug, Clone)]
+pub struct GasMeter {
+    limit: u64,
+    consumed: u64,
+    /// The operation that first exceeded the limit, if any.
+    out_of_gas_at: Option<&'static str>,
+}
+
+impl GasMeter {
+    pub fn new(limit: u64) -> Self {
+        GasMeter {
+            limit,
+            consumed: 0,
+            out_of_gas_at: None,
+        }
+    }
+
+    /// Consume `amount` units of gas, `descriptor` describes the operation consuming the gas.
+    pub fn consume(&mut self, amount: u64, descriptor: &'static str) {
+        self.consumed = self.consumed.saturating_add(amount);
+        if self.consumed > self.limit && self.out_of_gas_at.is_none() {
+            self.out_of_gas_at = Some(descriptor);
+        }
+    }
+
+    /// The gas limit of the meter.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// The gas consumed so far, which may exceed the limit.
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Returns the descriptor of the operation that exhausted the gas, or `None` if the limit has
+    /// not been exceeded.
+    pub fn out_of_gas(&self) -> Option<&'static str> {
+        self.out_of_gas_at
+    }
+}
+
+/// A storage wrapper that charges gas to the meter of a transaction and buffers its writes in
+/// the branch of the transaction. Without a meter nor a branch, the store is accessed directly.
+pub struct MeteredStore<'a, S: Storage> {
+    store: &'a S,
+    meter: Option<&'a Mutex<GasMeter>>,
+    branch: Option<&'a Branch>,
+    config: &'a GasConfig,
+}
+
+impl<'a, S: Storage> MeteredStore<'a, S> {
+    pub fn new(
+        store: &'a S,
+        meter: Option<&'a Mutex<GasMeter>>,
+        branch: Option<&'a Branch>,
+        config: &'a GasConfig,
+    ) -> Self {
+        MeteredStore {
+            store,
+            meter,
+            branch,
+            config,
+        }
+    }
+
+    fn consume(&self, amount: u64, descriptor: &'static str) {
+        if let Some(meter) = self.meter {
+            meter.lock().unwrap().consume(amount, descriptor);
+        }
+    }
+}
+
+impl<'a, S: Storage> std::fmt::Debug for MeteredStore<'a, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.store.fmt(f)
+    }
+}
+
+impl<'a, S: Storage> Storage for MeteredStore<'a, S> {
+    fn set(&self, path: Vec<u8>, value: Vec<u8>) {
+        self.consume(self.config.write_cost_flat, "WriteFlat");
+        self.consume(
+            self.config.write_cost_per_byte * (path.len() + value.len()) as u64,
+            "WritePerByte",
+        );
+        match self.branch {
+            Some(branch) => branch.set(path, value),
+            None => self.store.set(path, value),
+        }
+    }
+
+    fn get(&self, loc: Location, path: &[u8]) -> Option<Vec<u8>> {
+        self.consume(self.config.read_cost_flat, "ReadFlat");
+        let value = match self.branch {
+            Some(branch) => branch.get(self.store, loc, path)?,
+            None => self.store.get(loc, path)?,
+        };
+        self.consume(
+            self.config.read_cost_per_byte * value.len() as u64,
+            "ReadPerByte",
+        );
+        Some(value)
+    }
+
+    fn get_by_prefix(&self, loc: Location, prefix: &[u8]) -> Vec<PathValue> {
+        self.consume(self.config.read_cost_flat, "ReadFlat");
+        let values = self.store.get_by_prefix(loc, prefix);
+        for value in &values {
+            self.consume(self.config.iter_next_cost_flat, "IterNextFlat");
+            self.consume(
+                self.config.read_cost_per_byte * value.value.len() as u64,
+                "ReadPerByte",
+            );
+        }
+        values
+    }
+
+    fn root_hash(&self, loc: Location) -> Option<Vec<u8>> {
+        self.store.root_hash(loc)
+    }
+
+    fn grow(&self) {
+        self.store.grow()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Memory;
+
+    #[test]
+    fn metered_store() {
+        let store = Memory::new();
+        let config = GasConfig::default();
+
+        // No gas is charged without a meter
+        MeteredStore::new(&store, None, None, &config).set(b"foo".to_vec(), b"bar".to_vec());
+
+        let meter = Mutex::new(GasMeter::new(3000));
+        let branch = Branch::new();
+        let metered = MeteredStore::new(&store, Some(&meter), Some(&branch), &config);
+        metered.set(b"foo".to_vec(), b"baz".to_vec());
+        let consumed = meter.lock().unwrap().consumed();
+        assert_eq!(consumed, 2000 + 6 * 30);
+        assert_eq!(meter.lock().unwrap().out_of_gas(), None);
+
+        // Writes go to the branch
+        assert_eq!(
+            metered.get(Location::Pending, b"foo"),
+            Some(b"baz".to_vec())
+        );
+        assert_eq!(store.get(Location::Pending, b"foo"), Some(b"bar".to_vec()));
+        let gas_meter = meter.into_inner().unwrap();
+        assert_eq!(gas_meter.consumed(), 2000 + 6 * 30 + 1000 + 3 * 3);
+        assert_eq!(gas_meter.out_of_gas(), Some("ReadFlat"));
+    }
+}
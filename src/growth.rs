@@ -0,0 +1,441 @@
+//! # Growth
+//!
+//! This module controls the production of new blocks. Blocks are produced by `schedule_growth`
+//! following a `GrowthSchedule`, unless the growth is paused, and can also be requested on demand
+//! through a `GrowthHandle` or the admin JsonRPC methods.
+//!
+//! No block is produced at all (including by transactions and explicit requests) once the chain
+//! is halted, nor while it is stalled.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tokio::sync::Notify;
+
+use crate::node;
+use crate::store;
+
+/// The state shared between the node, its growth task and the growth handles.
+#[derive(Debug, Default)]
+pub struct GrowthControl {
+    state: Mutex<GrowthState>,
+    /// Notified each time the chain grows.
+    height_changed: Condvar,
+    /// Wakes the growth task when blocks are requested.
+    wake: Notify,
+}
+
+#[derive(Debug, Default)]
+struct GrowthState {
+    /// Whether the automatic growth is paused.
+    paused: bool,
+    /// The latest height of the chain.
+    height: u64,
+    /// The number of blocks requested but not yet produced.
+    requested_blocks: u64,
+    /// The end of the ongoing stall, if any.
+    stalled_until: Option<Instant>,
+    /// The height at which the chain halts, if any.
+    halt_height: Option<u64>,
+}
+
+impl GrowthState {
+    fn is_halted(&self) -> bool {
+        self.halt_height.map_or(false, |halt| self.height >= halt)
+    }
+}
+
+impl GrowthControl {
+    pub fn new() -> Self {
+        GrowthControl::default()
+    }
+
+    /// Pause the automatic growth of the chain, blocks can still be requested explicitly.
+    pub fn pause(&self) {
+        self.state.lock().unwrap().paused = true;
+    }
+
+    /// Resume the automatic growth of the chain.
+    pub fn resume(&self) {
+        self.state.lock().unwrap().paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Returns the latest height of the chain.
+    pub fn height(&self) -> u64 {
+        self.state.lock().unwrap().height
+    }
+
+    /// Stop producing blocks automatically for `duration`, the node reports that it is catching
+    /// up in the meantime.
+    pub fn stall(&self, duration: Duration) {
+        self.state.lock().unwrap().stalled_until = Some(Instant::now() + duration);
+        self.wake.notify_one();
+    }
+
+    /// Stop producing blocks once the chain reaches `height`.
+    pub fn halt_at(&self, height: Option<u64>) {
+        self.state.lock().unwrap().halt_height = height;
+        self.wake.notify_one();
+    }
+
+    /// Returns an error explaining why no block can be produced, if the chain is halted or
+    /// stalled.
+    pub fn check_growth(&self) -> Result<(), String> {
+        let state = self.state.lock().unwrap();
+        if state.is_halted() {
+            return Err(format!("the chain is halted at height {}", state.height));
+        }
+        match state.stalled_until {
+            Some(until) if until > Instant::now() => Err(format!(
+                "the chain is stalled for another {}ms",
+                (until - Instant::now()).as_millis()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the end of the ongoing stall, if any.
+    fn stalled_until(&self) -> Option<Instant> {
+        self.state
+            .lock()
+            .unwrap()
+            .stalled_until
+            .filter(|until| *until > Instant::now())
+    }
+
+    /// Whether the node is catching up, which is the case while the chain is stalled.
+    pub fn is_catching_up(&self) -> bool {
+        self.stalled_until().is_some()
+    }
+
+    /// Record a new height of the chain, called each time the chain grows.
+    ///
+    /// The growth task is woken up to schedule the next block from the new height, as the chain
+    /// can also grow through transactions.
+    pub fn record_height(&self, height: u64) {
+        self.state.lock().unwrap().height = height;
+        self.height_changed.notify_all();
+        self.wake.notify_one();
+    }
+
+    /// Request `count` new blocks from the growth task.
+    pub fn request_blocks(&self, count: u64) {
+        self.state.lock().unwrap().requested_blocks += count;
+        self.wake.notify_one();
+    }
+
+    /// Take one of the requested blocks, if any.
+    fn take_requested_block(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.requested_blocks > 0 {
+            state.requested_blocks -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Block until the chain reaches `height`, returns false if the timeout expired first.
+    pub fn wait_for_height(&self, height: u64, timeout: Duration) -> bool {
+        self.wait_until(timeout, |state| state.height >= height)
+    }
+
+    /// Block until `condition` holds, returns false if the timeout expired first.
+    fn wait_until<F>(&self, timeout: Duration, condition: F) -> bool
+    where
+        F: Fn(&GrowthState) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        while !condition(&state) {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .height_changed
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+        true
+    }
+}
+
+/// The intervals between two blocks produced automatically.
+#[derive(Clone, Debug, Default)]
+pub struct GrowthSchedule {
+    /// The base interval between two blocks, zero disables the automatic growth.
+    pub interval: Duration,
+    /// The maximum random deviation from the base interval.
+    pub jitter: Duration,
+    /// Intervals preceding the blocks at given heights, overriding the base interval and jitter.
+    pub overrides: BTreeMap<u64, Duration>,
+    /// The height at which the automatic growth stops, if any.
+    pub halt_height: Option<u64>,
+    /// Stalls starting once the chain reaches given heights, no block is produced automatically
+    /// during a stall.
+    pub stalls: BTreeMap<u64, Duration>,
+}
+
+impl GrowthSchedule {
+    /// Returns the interval preceding the block at `height`, or `None` if the block is not
+    /// produced automatically.
+    pub fn interval(&self, height: u64) -> Option<Duration> {
+        if self.halt_height.map_or(false, |halt| height > halt) {
+            return None;
+        }
+        if let Some(interval) = self.overrides.get(&height) {
+            return Some(*interval);
+        }
+        if self.interval == Duration::from_secs(0) {
+            return None;
+        }
+        if self.jitter == Duration::from_secs(0) {
+            return Some(self.interval);
+        }
+        // Uniform deviation within [-jitter, jitter]
+        let jitter = self.jitter.as_nanos() as i128;
+        let deviation = rand::thread_rng().gen_range(-jitter..=jitter);
+        let interval = (self.interval.as_nanos() as i128 + deviation).max(0);
+        Some(Duration::from_nanos(interval as u64))
+    }
+}
+
+/// A handle to control the block production of a running Tendermock node.
+///
+/// The handle can be obtained from the builder before the node is started, requests made before
+/// the node starts are served once it is running.
+#[derive(Clone, Debug)]
+pub struct GrowthHandle {
+    control: Arc<GrowthControl>,
+}
+
+impl GrowthHandle {
+    pub(crate) fn new(control: Arc<GrowthControl>) -> Self {
+        GrowthHandle { control }
+    }
+
+    /// Produce `count` new blocks and block until they are added to the chain, or until
+    /// `timeout` expires, in which case false is returned. The blocks requested during a stall
+    /// are produced once it ends, and no block is produced once the chain is halted.
+    pub fn produce_blocks(&self, count: u64, timeout: Duration) -> bool {
+        if self.control.state.lock().unwrap().is_halted() {
+            return false;
+        }
+        let target = self.control.height() + count;
+        self.control.request_blocks(count);
+        self.control.wait_until(timeout, |state| {
+            state.requested_blocks == 0 && state.height >= target
+        })
+    }
+
+    /// Pause the automatic growth of the chain.
+    pub fn pause(&self) {
+        self.control.pause();
+    }
+
+    /// Resume the automatic growth of the chain.
+    pub fn resume(&self) {
+        self.control.resume();
+    }
+
+    /// Returns the latest height of the chain.
+    pub fn height(&self) -> u64 {
+        self.control.height()
+    }
+
+    /// Block until the chain reaches `height`, returns false if `timeout` expired first.
+    pub fn wait_for_height(&self, height: u64, timeout: Duration) -> bool {
+        self.control.wait_for_height(height, timeout)
+    }
+
+    /// Stop producing blocks automatically for `duration`.
+    pub fn stall(&self, duration: Duration) {
+        self.control.stall(duration);
+    }
+}
+
+/// Push new blocks on the chain following `schedule`, unless the growth is paused or stalled,
+/// and produce the requested blocks.
+pub async fn schedule_growth<S: store::Storage>(
+    node: node::SharedNode<S>,
+    schedule: GrowthSchedule,
+) -> Result<(), std::convert::Infallible> {
+    let control = node.read().growth().clone();
+    control.halt_at(schedule.halt_height);
+    let grow = || {
+        node.grow();
+        if let Some(duration) = schedule.stalls.get(&control.height()) {
+            control.stall(*duration);
+        }
+    };
+    // The next block is produced one interval after the latest block or the end of the stall
+    let next_block_time = || {
+        let interval = schedule.interval(control.height() + 1)?;
+        let start = control.stalled_until().unwrap_or_else(Instant::now);
+        Some(tokio::time::Instant::from_std(start + interval))
+    };
+
+    grow();
+    let mut next_block = next_block_time();
+    loop {
+        // Produce the requested blocks first, they are kept until the end of a stall
+        while control.check_growth().is_ok() && control.take_requested_block() {
+            grow();
+        }
+        // Without automatic growth, wake up at the end of the stall for the requested blocks
+        let stall_end = control.stalled_until().map(tokio::time::Instant::from_std);
+
+        match next_block.or(stall_end) {
+            None => control.wake.notified().await,
+            Some(deadline) => tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {
+                    if next_block.is_some() && !control.is_paused() {
+                        grow();
+                    }
+                }
+                _ = control.wake.notified() => {}
+            },
+        }
+        next_block = next_block_time();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::node::Node;
+
+    #[test]
+    fn handle() {
+        let control = Arc::new(GrowthControl::new());
+        let handle = GrowthHandle::new(control.clone());
+        let node = Node::new(&Config::default())
+            .with_growth_control(control)
+            .shared();
+        assert_eq!(handle.height(), 1);
+
+        let schedule = GrowthSchedule {
+            interval: Duration::from_millis(100),
+            ..GrowthSchedule::default()
+        };
+
+        // Blocks requested before the growth task starts are produced once it runs
+        let timeout = Duration::from_secs(5);
+        handle.pause();
+        let producer = {
+            let handle = handle.clone();
+            std::thread::spawn(move || handle.produce_blocks(3, timeout))
+        };
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(schedule_growth(node, schedule))
+        });
+        assert!(producer.join().unwrap());
+        assert!(handle.wait_for_height(5, timeout));
+        assert!(handle.produce_blocks(2, timeout));
+        assert_eq!(handle.height(), 7);
+
+        // The automatic growth is paused, waits for unreached heights time out
+        assert!(!handle.wait_for_height(8, Duration::from_secs(0)));
+        assert_eq!(handle.height(), 7);
+        handle.resume();
+        assert!(handle.wait_for_height(8, timeout));
+    }
+
+    #[test]
+    fn schedule() {
+        let mut schedule = GrowthSchedule::default();
+        assert_eq!(schedule.interval(2), None);
+
+        schedule.overrides.insert(5, Duration::from_secs(30));
+        assert_eq!(schedule.interval(2), None);
+        assert_eq!(schedule.interval(5), Some(Duration::from_secs(30)));
+
+        schedule.interval = Duration::from_millis(100);
+        assert_eq!(schedule.interval(2), Some(Duration::from_millis(100)));
+
+        schedule.jitter = Duration::from_millis(50);
+        for height in 2..100 {
+            let interval = schedule.interval(height).unwrap();
+            assert!(interval >= Duration::from_millis(50));
+            assert!(interval <= Duration::from_millis(150));
+        }
+        assert_eq!(schedule.interval(5), Some(Duration::from_secs(30)));
+
+        schedule.halt_height = Some(10);
+        assert!(schedule.interval(10).is_some());
+        assert_eq!(schedule.interval(11), None);
+    }
+
+    #[test]
+    fn zero_interval() {
+        let control = Arc::new(GrowthControl::new());
+        let handle = GrowthHandle::new(control.clone());
+        let node = Node::new(&Config::default())
+            .with_growth_control(control)
+            .shared();
+        let mut schedule = GrowthSchedule::default();
+        schedule.overrides.insert(3, Duration::from_millis(10));
+        schedule.overrides.insert(5, Duration::from_millis(10));
+        {
+            let node = node.clone();
+            std::thread::spawn(move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(schedule_growth(node, schedule))
+            });
+        }
+
+        // Only the blocks with an interval override are produced automatically
+        let timeout = Duration::from_secs(5);
+        assert!(handle.wait_for_height(3, timeout));
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(handle.height(), 3);
+
+        // Including once the chain reaches the preceding height through a transaction
+        assert!(node.grow());
+        assert!(handle.wait_for_height(5, timeout));
+    }
+
+    #[test]
+    fn stall() {
+        let control = GrowthControl::new();
+        assert!(!control.is_catching_up());
+        assert!(control.check_growth().is_ok());
+        control.stall(Duration::from_secs(3600));
+        assert!(control.is_catching_up());
+        assert!(control.check_growth().is_err());
+
+        // A stall replaces the ongoing one, an empty stall ends it
+        control.stall(Duration::from_secs(0));
+        assert!(!control.is_catching_up());
+        assert!(control.check_growth().is_ok());
+    }
+
+    #[test]
+    fn halt() {
+        let control = Arc::new(GrowthControl::new());
+        let node = Node::new(&Config::default())
+            .with_growth_control(control.clone())
+            .shared();
+        control.halt_at(Some(3));
+        assert!(node.grow());
+        assert!(node.grow());
+        assert_eq!(control.height(), 3);
+
+        // Neither transactions nor explicit requests produce blocks once halted
+        assert!(!node.grow());
+        assert_eq!(control.height(), 3);
+        assert!(control.check_growth().is_err());
+        assert!(!GrowthHandle::new(control).produce_blocks(1, Duration::from_secs(5)));
+    }
+}
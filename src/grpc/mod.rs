@@ -11,12 +11,13 @@
 //!
 //! The server code is also generated, this time by [tonic](https://github.com/hyperium/tonic) and
 //! it also lives in the `ibc_proto` crate. This module simply implements the `Query` trait
-//! generated by `Tonic` on a custom `QueryService` struct.
+//! generated by `Tonic` on a custom `QueryService` struct, and the `Service` trait of the Cosmos
+//! SDK tx service on a `TxService` struct.
 
 use futures::future::FutureExt;
 use tonic::transport::Server;
 
-use service::{auth, client, staking};
+use service::{auth, client, staking, tx};
 
 use crate::logger::Log;
 use crate::node;
@@ -35,7 +36,8 @@ pub async fn serve<S: 'static + Storage + Sync + Send>(
     Server::builder()
         .add_service(staking::get_service(node.clone()))
         .add_service(auth::get_service(node.clone()))
-        .add_service(client::get_service(node))
+        .add_service(client::get_service(node.clone()))
+        .add_service(tx::get_service(node))
         .serve(addr)
         .then(|result| async {
             if let Err(e) = result {
@@ -8,9 +8,10 @@ use prost::Message;
 use prost_types::Any;
 use tonic::{Request, Response, Status};
 
+use crate::accounts;
 use crate::logger::Log;
 use crate::node;
-use crate::store::Storage;
+use crate::store::{Location, Storage};
 
 pub fn get_service<S: 'static + Storage + Sync + Send>(
     node: node::SharedNode<S>,
@@ -22,7 +23,6 @@ pub fn get_service<S: 'static + Storage + Sync + Send>(
 /// A struct handling the `Query` service.
 #[derive(Clone)]
 pub struct QueryService<S: Storage> {
-    #[allow(dead_code)]
     node: node::SharedNode<S>,
 }
 
@@ -39,21 +39,19 @@ impl<S: 'static + Storage + Sync + Send> Query for QueryService<S> {
         request: Request<v1beta1::QueryAccountRequest>,
     ) -> Result<Response<v1beta1::QueryAccountResponse>, Status> {
         log!(Log::Grpc, "/auth/account {:?}", request);
-        let base_account = v1beta1::BaseAccount {
-            address: String::from("ACCOUNT_ADDRESS"),
-            pub_key: None,
-            account_number: 42,
-            sequence: 42,
-        };
+        let address = request.into_inner().address;
+        let node = self.node.read();
+        let base_account =
+            accounts::get_account(&node.store(), Location::LatestStable, &address)
+                .ok_or_else(|| Status::not_found(format!("account {} not found", address)))?;
         let mut buffer = Vec::new();
         base_account.encode(&mut buffer).unwrap();
         let response = v1beta1::QueryAccountResponse {
             account: Some(Any {
-                type_url: String::from("TODO!"), // TODO: What is the `BaseAccount` url?
+                type_url: String::from(accounts::BASE_ACCOUNT_TYPE_URL),
                 value: buffer,
             }),
         };
-        //let response = v1beta1::QueryAccountResponse { account: None };
         Ok(Response::new(response))
     }
 
@@ -1,14 +1,32 @@
 //! # gRPC Staking
 //!
 //! The staking tendermint gRPC API.
+//!
+//! The validators are the ones signing the latest block of the chain, all of them are bonded.
+use ibc_proto::cosmos::base::query::v1beta1::PageResponse;
 use ibc_proto::cosmos::staking::v1beta1;
 use ibc_proto::cosmos::staking::v1beta1::query_server::{Query, QueryServer};
+use prost::Message;
+use prost_types::Any;
+use tendermint::validator;
 use tonic::{Request, Response, Status};
 
 use crate::logger::Log;
 use crate::node;
 use crate::store::Storage;
 
+const ED25519_PUB_KEY_TYPE_URL: &str = "/cosmos.crypto.ed25519.PubKey";
+const BONDED: &str = "BOND_STATUS_BONDED";
+/// The number of tokens per unit of voting power, as in the Cosmos SDK.
+const POWER_REDUCTION: u64 = 1_000_000;
+
+/// The `cosmos.crypto.ed25519.PubKey` protobuf message.
+#[derive(Clone, PartialEq, Message)]
+struct Ed25519PubKey {
+    #[prost(bytes, tag = "1")]
+    key: Vec<u8>,
+}
+
 pub fn get_service<S: 'static + Storage + Sync + Send>(
     node: node::SharedNode<S>,
 ) -> QueryServer<QueryService<S>> {
@@ -19,7 +37,6 @@ pub fn get_service<S: 'static + Storage + Sync + Send>(
 /// A struct handling the `Query` service.
 #[derive(Clone)]
 pub struct QueryService<S: Storage> {
-    #[allow(dead_code)]
     node: node::SharedNode<S>,
 }
 
@@ -27,6 +44,49 @@ impl<S: Storage> QueryService<S> {
     fn new(node: node::SharedNode<S>) -> Self {
         QueryService { node }
     }
+
+    /// Returns the current validators, in their staking module representation.
+    fn get_validators(&self) -> Vec<v1beta1::Validator> {
+        let node = self.node.read();
+        let block = node
+            .chain()
+            .get_block(0)
+            .expect("The chain should always contain a block");
+        let prefix = format!("{}valoper", node.account_prefix());
+        block
+            .validators
+            .validators()
+            .iter()
+            .map(|info| to_staking_validator(info, &prefix))
+            .collect()
+    }
+}
+
+/// Build the staking representation of a Tendermint validator.
+fn to_staking_validator(info: &validator::Info, prefix: &str) -> v1beta1::Validator {
+    let mut pub_key = Vec::new();
+    Ed25519PubKey {
+        key: info.pub_key.to_bytes(),
+    }
+    .encode(&mut pub_key)
+    .unwrap();
+    let tokens = info.voting_power.value() * POWER_REDUCTION;
+    v1beta1::Validator {
+        operator_address: subtle_encoding::bech32::encode(prefix, info.address.as_bytes()),
+        consensus_pubkey: Some(Any {
+            type_url: ED25519_PUB_KEY_TYPE_URL.to_owned(),
+            value: pub_key,
+        }),
+        jailed: false,
+        status: v1beta1::BondStatus::Bonded as i32,
+        tokens: tokens.to_string(),
+        delegator_shares: format!("{}.000000000000000000", tokens),
+        description: None,
+        unbonding_height: 0,
+        unbonding_time: None,
+        commission: None,
+        min_self_delegation: String::from("1"),
+    }
 }
 
 #[tonic::async_trait]
@@ -36,7 +96,20 @@ impl<S: 'static + Storage + Sync + Send> Query for QueryService<S> {
         request: Request<v1beta1::QueryValidatorsRequest>,
     ) -> Result<Response<v1beta1::QueryValidatorsResponse>, Status> {
         log!(Log::Grpc, "/staking/validators {:?}", request);
-        unimplemented!();
+        let status = request.into_inner().status;
+        let validators = if status.is_empty() || status == BONDED {
+            self.get_validators()
+        } else {
+            vec![]
+        };
+        let response = v1beta1::QueryValidatorsResponse {
+            pagination: Some(PageResponse {
+                next_key: vec![],
+                total: validators.len() as u64,
+            }),
+            validators,
+        };
+        Ok(Response::new(response))
     }
 
     async fn validator(
@@ -44,7 +117,16 @@ impl<S: 'static + Storage + Sync + Send> Query for QueryService<S> {
         request: Request<v1beta1::QueryValidatorRequest>,
     ) -> Result<Response<v1beta1::QueryValidatorResponse>, Status> {
         log!(Log::Grpc, "/staking/validator {:?}", request);
-        unimplemented!();
+        let address = request.into_inner().validator_addr;
+        let validator = self
+            .get_validators()
+            .into_iter()
+            .find(|v| v.operator_address == address)
+            .ok_or_else(|| Status::not_found(format!("validator {} not found", address)))?;
+        let response = v1beta1::QueryValidatorResponse {
+            validator: Some(validator),
+        };
+        Ok(Response::new(response))
     }
 
     async fn validator_delegations(
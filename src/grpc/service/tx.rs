@@ -0,0 +1,224 @@
+//! # gRPC Tx
+//!
+//! The Cosmos SDK tx gRPC service, backed by the same transaction pipeline as the JsonRPC API.
+//!
+//! Broadcasted transactions are always committed in a new block, whatever the requested
+//! broadcast mode.
+
+use ibc_proto::cosmos::base::abci::v1beta1::{
+    AbciMessageLog, Attribute, GasInfo, Result as AbciResult, StringEvent, TxResponse,
+};
+use ibc_proto::cosmos::tx::v1beta1;
+use ibc_proto::cosmos::tx::v1beta1::service_server::{Service, ServiceServer};
+use prost::Message;
+use prost_types::Any;
+use tendermint::abci::transaction::Hash;
+use tendermint::abci::Event;
+use tendermint::block;
+use tendermint_rpc::endpoint::broadcast::tx_commit::TxResult;
+use tonic::{Request, Response, Status};
+
+use crate::logger::Log;
+use crate::node;
+use crate::store::Storage;
+use crate::tx::{self, HASH_LENGTH};
+
+const TX_TYPE_URL: &str = "/cosmos.tx.v1beta1.Tx";
+
+pub fn get_service<S: 'static + Storage + Sync + Send>(
+    node: node::SharedNode<S>,
+) -> ServiceServer<TxService<S>> {
+    let tx_service = TxService::new(node);
+    ServiceServer::new(tx_service)
+}
+
+/// A struct handling the `Service` service.
+#[derive(Clone)]
+pub struct TxService<S: Storage> {
+    node: node::SharedNode<S>,
+}
+
+impl<S: Storage> TxService<S> {
+    fn new(node: node::SharedNode<S>) -> Self {
+        TxService { node }
+    }
+
+    /// Build the SDK `TxResponse` of a transaction included at `height`.
+    fn tx_response(
+        &self,
+        hash: Hash,
+        raw: Vec<u8>,
+        height: block::Height,
+        result: &TxResult,
+    ) -> TxResponse {
+        let node = self.node.read();
+        let timestamp = node
+            .chain()
+            .get_block(height.value())
+            .map(|block| block.signed_header.header.time.to_rfc3339())
+            .unwrap_or_default();
+        let logs = if result.code.is_ok() {
+            vec![AbciMessageLog {
+                msg_index: 0,
+                log: String::new(),
+                events: result.events.iter().map(to_string_event).collect(),
+            }]
+        } else {
+            vec![]
+        };
+        TxResponse {
+            height: height.value() as i64,
+            txhash: hash.to_string(),
+            codespace: codespace(result),
+            code: result.code.value(),
+            data: result
+                .data
+                .as_ref()
+                .map(|data| hex::encode_upper(data.value()))
+                .unwrap_or_default(),
+            raw_log: result.log.to_string(),
+            logs,
+            info: result.info.to_string(),
+            gas_wanted: result.gas_wanted.value() as i64,
+            gas_used: result.gas_used.value() as i64,
+            // A `TxRaw` has the same encoding as the corresponding `Tx`
+            tx: Some(Any {
+                type_url: TX_TYPE_URL.to_owned(),
+                value: raw,
+            }),
+            timestamp,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<S: 'static + Storage + Sync + Send> Service for TxService<S> {
+    async fn simulate(
+        &self,
+        request: Request<v1beta1::SimulateRequest>,
+    ) -> Result<Response<v1beta1::SimulateResponse>, Status> {
+        log!(Log::Grpc, "/tx/simulate {:?}", request);
+        let tx = request
+            .into_inner()
+            .tx
+            .ok_or_else(|| Status::invalid_argument("missing tx"))?;
+        let mut raw = Vec::new();
+        tx.encode(&mut raw).unwrap();
+        let result = tx::simulate_tx(&self.node, &raw);
+        if result.code.is_err() {
+            return Err(Status::invalid_argument(result.log.to_string()));
+        }
+        let response = v1beta1::SimulateResponse {
+            gas_info: Some(GasInfo {
+                gas_wanted: result.gas_wanted.value(),
+                gas_used: result.gas_used.value(),
+            }),
+            result: Some(AbciResult {
+                data: result
+                    .data
+                    .as_ref()
+                    .map(|data| data.value().to_vec())
+                    .unwrap_or_default(),
+                log: result.log.to_string(),
+                events: result.events.iter().map(to_proto_event).collect(),
+            }),
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn get_tx(
+        &self,
+        request: Request<v1beta1::GetTxRequest>,
+    ) -> Result<Response<v1beta1::GetTxResponse>, Status> {
+        log!(Log::Grpc, "/tx/get_tx {:?}", request);
+        let hash = request.into_inner().hash;
+        let hash_bytes = hex::decode(&hash)
+            .ok()
+            .filter(|bytes| bytes.len() == HASH_LENGTH)
+            .ok_or_else(|| Status::invalid_argument(format!("invalid tx hash {}", hash)))?;
+        let mut hash_array = [0; HASH_LENGTH];
+        hash_array.copy_from_slice(&hash_bytes);
+        let hash = Hash::new(hash_array);
+        let indexed = self
+            .node
+            .read()
+            .get_tx(&hash)
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("tx not found: {}", hash)))?;
+        let raw: Vec<u8> = indexed.tx.into();
+        let tx = v1beta1::Tx::decode(raw.as_slice())
+            .map_err(|e| Status::internal(format!("failed to decode tx: {}", e)))?;
+        let tx_response = self.tx_response(hash, raw, indexed.height, &indexed.result);
+        Ok(Response::new(v1beta1::GetTxResponse {
+            tx: Some(tx),
+            tx_response: Some(tx_response),
+        }))
+    }
+
+    async fn broadcast_tx(
+        &self,
+        request: Request<v1beta1::BroadcastTxRequest>,
+    ) -> Result<Response<v1beta1::BroadcastTxResponse>, Status> {
+        log!(Log::Grpc, "/tx/broadcast_tx {:?}", request);
+        let raw = request.into_inner().tx_bytes;
+        let committed =
+            tx::broadcast_tx_commit(&self.node, raw.clone().into()).map_err(Status::unavailable)?;
+        // Transactions failing the checks are reported with their `CheckTx` result
+        let result = if committed.check_tx.code.is_err() {
+            &committed.check_tx
+        } else {
+            &committed.deliver_tx
+        };
+        let tx_response = self.tx_response(committed.hash, raw, committed.height, result);
+        Ok(Response::new(v1beta1::BroadcastTxResponse {
+            tx_response: Some(tx_response),
+        }))
+    }
+
+    async fn get_txs_event(
+        &self,
+        request: Request<v1beta1::GetTxsEventRequest>,
+    ) -> Result<Response<v1beta1::GetTxsEventResponse>, Status> {
+        log!(Log::Grpc, "/tx/get_txs_event {:?}", request);
+        Err(Status::unimplemented(
+            "querying txs by event is not supported",
+        ))
+    }
+}
+
+/// Returns the codespace of a result, which can only be read through its serialization.
+fn codespace(result: &TxResult) -> String {
+    match serde_json::to_value(&result.codespace) {
+        Ok(serde_json::Value::String(codespace)) => codespace,
+        _ => String::new(),
+    }
+}
+
+fn to_proto_event(event: &Event) -> tendermint_proto::abci::Event {
+    tendermint_proto::abci::Event {
+        r#type: event.type_str.clone(),
+        attributes: event
+            .attributes
+            .iter()
+            .map(|tag| tendermint_proto::abci::EventAttribute {
+                key: tag.key.to_string().into_bytes(),
+                value: tag.value.to_string().into_bytes(),
+                index: true,
+            })
+            .collect(),
+    }
+}
+
+fn to_string_event(event: &Event) -> StringEvent {
+    StringEvent {
+        r#type: event.type_str.clone(),
+        attributes: event
+            .attributes
+            .iter()
+            .map(|tag| Attribute {
+                key: tag.key.to_string(),
+                value: tag.value.to_string(),
+            })
+            .collect(),
+    }
+}
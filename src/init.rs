@@ -1,7 +1,7 @@
 //! # Storage initialization
 //!
 //! This modules initializes the storage, by inserting values into the node using the ICS26
-//! interface.
+//! interface and the accounts module.
 //!
 //! The initial values are taken from the configuration (see `config` module).
 use std::str::FromStr;
@@ -13,19 +13,27 @@ use ibc::{
 };
 use tendermint::trust_threshold::TrustThresholdFraction;
 
+use crate::accounts;
 use crate::config::{Client, Config};
+use crate::node::SharedNode;
+use crate::store::Storage;
+use crate::upgrade;
 
-/// Initialize the client keeper by registering all the client present in the configuration.
-pub fn init<T: ClientKeeper>(keeper: &mut T, config: &Config) {
+/// Initialize the node by registering all the clients and accounts present in the configuration.
+pub fn init<S: Storage>(node: &mut SharedNode<S>, config: &Config) {
     for client in &config.clients {
-        add_client(keeper, client, config);
+        add_client(node, client, config);
+    }
+    for account in &config.accounts {
+        accounts::new_account(&node.read().store(), account.address.clone(), None);
     }
 }
 
 fn add_client<T: ClientKeeper>(keeper: &mut T, client: &Client, config: &Config) {
     let client_id = ClientId::from_str(&client.id)
         .unwrap_or_else(|_| panic!("Invalid client id: {}", &client.id));
-    let client_state = new_client_state(config);
+    let height = Height::new(upgrade::revision_number(&config.chain_id), 1);
+    let client_state = new_client_state(&config.chain_id, height, height);
     keeper
         .store_client_state(client_id.clone(), client_state)
         .unwrap();
@@ -34,18 +42,22 @@ fn add_client<T: ClientKeeper>(keeper: &mut T, client: &Client, config: &Config)
         .unwrap();
 }
 
-fn new_client_state(config: &Config) -> AnyClientState {
+/// Build a Tendermint client state of the chain `chain_id` at `height`, frozen at `frozen_height`
+/// (a zero height for clients that are not frozen).
+pub fn new_client_state(chain_id: &str, height: Height, frozen_height: Height) -> AnyClientState {
     let duration = std::time::Duration::new(3600 * 24 * 30, 0);
-    let height = Height::new(1, 1);
     let client_state = ClientState {
-        chain_id: String::from(&config.chain_id).parse().unwrap(),
+        chain_id: chain_id.parse().unwrap(),
         trusting_period: duration,
         trust_level: TrustThresholdFraction::new(1, 3).unwrap(),
         unbonding_period: duration,
         max_clock_drift: duration,
-        frozen_height: height,
+        frozen_height,
         latest_height: height,
-        upgrade_path: vec![String::from("path")],
+        upgrade_path: vec![
+            String::from("upgrade"),
+            String::from(upgrade::UPGRADED_IBC_STATE),
+        ],
         allow_update: AllowUpdate {
             after_expiry: false,
             after_misbehaviour: false,
@@ -1,36 +1,175 @@
 //! The Tendermock JsonRPC HTTP API.
+use std::convert::TryFrom;
 
-use ibc::events::IbcEvent;
-use ibc::ics26_routing::handler::deliver;
-use ibc_proto::cosmos::tx::v1beta1::{TxBody, TxRaw};
-use prost::Message;
-use tendermint::abci::responses::Codespace;
-use tendermint::abci::tag::Tag;
-use tendermint::abci::{transaction::Hash, Code, Event, Info};
+use serde::{Deserialize, Serialize};
+use tendermint::abci::{transaction::Hash, Transaction};
+use tendermint::block;
+use tendermint_proto::Protobuf;
 use tendermint_rpc::endpoint::{
     abci_info::Request as AbciInfoRequest, abci_info::Response as AbciInfoResponse,
     abci_query::Request as AbciQueryRequest, abci_query::Response as AbciQueryResponse,
     block::Request as BlockRequest, block::Response as BlockResponse,
+    blockchain::Response as BlockchainResponse,
     broadcast::tx_commit::Request as BroadcastTxCommitRequest,
     broadcast::tx_commit::Response as BroadcastTxCommitResponse, broadcast::tx_commit::TxResult,
     commit::Request as CommitRequest, commit::Response as CommitResponse,
     genesis::Request as GenesisRequest, genesis::Response as GenesisResponse,
-    status::Request as StatusRequest, status::Response as StatusResponse,
-    validators::Request as ValidatorsRequest, validators::Response as ValidatorResponse,
+    status::Request as StatusRequest, validators::Response as ValidatorResponse,
 };
+use tendermint_testgen::light_block::TmLightBlock;
 
 use crate::abci;
 use crate::chain::to_full_block;
 use crate::logger::Log;
 use crate::node;
 use crate::store;
+use crate::tx::{self, HASH_LENGTH};
 
-use super::utils::{JrpcError, JrpcFilter, JrpcResult};
+use super::utils::{JrpcError, JrpcFilter, JrpcMethods, JrpcResult};
 
-const PUBLICK_KEY: &str = "4A25C6640A1F72B9C975338294EF51B6D1C33158BB6ECBA69FBC3FB5A33C9DCE";
-const HASH_LENGTH: usize = 32; // tendermint::abci::transaction::hash::LENGTH is not exposed...
+/// Default and maximum number of items per page, as in Tendermint.
+const DEFAULT_PER_PAGE: usize = 30;
+const MAX_PER_PAGE: usize = 100;
+/// Maximum number of block metas returned by the /blockchain endpoint, as in Tendermint.
+const MAX_BLOCKCHAIN_BLOCKS: u64 = 20;
 
-/// A structure to build the JsonRPC HTTP API, see the `new` method.
+/// Response of the /status endpoint, with the sync info of the node.
+#[derive(Serialize, Debug)]
+pub struct StatusResponse {
+    pub node_info: tendermint::node::Info,
+    pub sync_info: node::SyncInfo,
+    pub validator_info: tendermint::validator::Info,
+}
+
+/// Parameters of the /validators endpoint.
+#[derive(Deserialize, Debug)]
+pub struct ValidatorsRequest {
+    /// The height of the validator set, the latest height if omitted.
+    pub height: Option<block::Height>,
+    #[serde(default, deserialize_with = "optional_usize")]
+    pub page: Option<usize>,
+    #[serde(default, deserialize_with = "optional_usize")]
+    pub per_page: Option<usize>,
+}
+
+/// Parameters of the /blockchain endpoint.
+#[derive(Deserialize, Debug)]
+pub struct BlockchainRequest {
+    /// The lowest height, the first block if omitted.
+    #[serde(rename = "minHeight")]
+    pub min_height: Option<block::Height>,
+    /// The highest height, the latest block if omitted.
+    #[serde(rename = "maxHeight")]
+    pub max_height: Option<block::Height>,
+}
+
+/// Parameters of the /block_by_hash and /header_by_hash endpoints.
+#[derive(Deserialize, Debug)]
+pub struct ByHashRequest {
+    /// The hash of the block, either hex or base64 encoded.
+    pub hash: String,
+}
+
+/// Parameters of the /header endpoint.
+#[derive(Deserialize, Debug)]
+pub struct HeaderRequest {
+    /// The height of the header, the latest height if omitted.
+    pub height: Option<block::Height>,
+}
+
+/// Response of the /header endpoint.
+#[derive(Serialize)]
+pub struct HeaderResponse {
+    pub header: block::Header,
+}
+
+/// Response of the /block_by_hash endpoint, the block is null and its id is empty if the hash is
+/// unknown.
+#[derive(Serialize)]
+pub struct BlockByHashResponse {
+    pub block_id: block::Id,
+    pub block: Option<tendermint::Block>,
+}
+
+/// Response of the /header_by_hash endpoint, the header is null if the hash is unknown.
+#[derive(Serialize)]
+pub struct HeaderByHashResponse {
+    pub header: Option<block::Header>,
+}
+
+/// Parameters of the admin methods that take no parameter.
+#[derive(Deserialize, Debug)]
+pub struct EmptyRequest {}
+
+/// Parameters of the /admin_produce_blocks method.
+#[derive(Deserialize, Debug)]
+pub struct ProduceBlocksRequest {
+    /// The number of blocks to produce.
+    pub count: u64,
+}
+
+/// Parameters of the /admin_wait_for_height method.
+#[derive(Deserialize, Debug)]
+pub struct WaitForHeightRequest {
+    pub height: u64,
+    /// The maximum time to wait, in milliseconds.
+    #[serde(default = "default_wait_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+/// Parameters of the /admin_stall method.
+#[derive(Deserialize, Debug)]
+pub struct StallRequest {
+    /// The duration of the stall, in milliseconds.
+    pub duration_ms: u64,
+}
+
+/// Parameters of the /admin_conflicting_header method.
+#[derive(Deserialize, Debug)]
+pub struct ConflictingHeaderRequest {
+    pub height: block::Height,
+    /// The app hash of the conflicting header (hex encoded), derived from the original header by
+    /// default.
+    #[serde(default)]
+    pub app_hash: Option<String>,
+}
+
+/// Response of the /admin_conflicting_header method.
+#[derive(Serialize)]
+pub struct ConflictingHeaderResponse {
+    pub signed_header: tendermint::block::signed_header::SignedHeader,
+    pub validators: tendermint::validator::Set,
+    pub next_validators: tendermint::validator::Set,
+}
+
+/// Response of the admin methods, holding the latest height of the chain.
+#[derive(Serialize)]
+pub struct GrowthResponse {
+    pub height: block::Height,
+    pub paused: bool,
+    pub catching_up: bool,
+}
+
+/// Parameters of the /tx endpoint.
+#[derive(Deserialize, Debug)]
+pub struct TxRequest {
+    /// The base64 encoded hash of the transaction.
+    pub hash: String,
+    #[serde(default)]
+    pub prove: bool,
+}
+
+/// Response of the /tx endpoint.
+#[derive(Serialize)]
+pub struct TxResponse {
+    pub hash: Hash,
+    pub height: block::Height,
+    pub index: u32,
+    pub tx_result: TxResult,
+    pub tx: Transaction,
+}
+
+/// A structure to build the JsonRPC API, see the `methods` method.
 pub struct Jrpc<S: store::Storage>
 where
     node::SharedNode<S>: Clone,
@@ -52,13 +191,16 @@ where
     S: 'static + store::Storage,
     node::SharedNode<S>: Sync + Send + Clone,
 {
-    /// Creates a new `warp` filter that mimics Tendermint's JsonRPC HTTP API.
-    pub fn new_mimic(
-        node: node::SharedNode<S>,
-    ) -> impl warp::Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    /// Returns the JsonRPC methods of Tendermint's API, which are served over both HTTP and
+    /// websockets.
+    pub fn methods(node: node::SharedNode<S>) -> JrpcMethods<Self> {
         let state = Self { node };
         JrpcFilter::new(state)
             .add("block", Self::block)
+            .add("blockchain", Self::blockchain)
+            .add("block_by_hash", Self::block_by_hash)
+            .add("header", Self::header)
+            .add("header_by_hash", Self::header_by_hash)
             .add("commit", Self::commit)
             .add("genesis", Self::genesis)
             .add("validators", Self::validators)
@@ -66,48 +208,134 @@ where
             .add("abci_info", Self::abci_info)
             .add("abci_query", Self::abci_query)
             .add("broadcast_tx_commit", Self::broadcast_tx_commit)
-            .build()
+            .add("tx", Self::tx)
+            .add("admin_produce_blocks", Self::admin_produce_blocks)
+            .add("admin_pause_growth", Self::admin_pause_growth)
+            .add("admin_resume_growth", Self::admin_resume_growth)
+            .add("admin_wait_for_height", Self::admin_wait_for_height)
+            .add("admin_stall", Self::admin_stall)
+            .add("admin_conflicting_header", Self::admin_conflicting_header)
+            .into_methods()
     }
 
     /// JsonRPC /block endpoint.
     fn block(req: BlockRequest, state: Self) -> JrpcResult<BlockResponse> {
         log!(Log::Jrpc, "/block      {:?}", req);
-        let height = match req.height {
-            None => 0,
-            Some(height) => height.into(),
-        };
         let node = state.node.read();
-        let block = node
-            .chain()
-            .get_block(height)
-            .ok_or(JrpcError::InvalidRequest)?;
-        let tm_block = to_full_block(block);
-        let hash = tm_block.header.hash();
+        let block = get_block(&node, req.height)?;
+        let txs = node.get_block_txs(block.signed_header.header.height);
+        let tm_block = to_full_block(block, txs);
         Ok(BlockResponse {
-            block_id: tendermint::block::Id {
-                part_set_header: tendermint::block::parts::Header::new(1, hash).unwrap(),
-                hash,
-            },
+            block_id: block_id(tm_block.header.hash()),
             block: tm_block,
         })
     }
 
+    /// JsonRPC /block_by_hash endpoint.
+    fn block_by_hash(req: ByHashRequest, state: Self) -> JrpcResult<BlockByHashResponse> {
+        log!(Log::Jrpc, "/block_by_hash {:?}", req);
+        let hash = parse_block_hash(&req.hash).ok_or(JrpcError::WrongParameters)?;
+        let node = state.node.read();
+        // Like Tendermint, unknown hashes are not an error
+        let block = match node.chain().get_block_by_hash(&hash) {
+            Some(block) => block,
+            None => {
+                return Ok(BlockByHashResponse {
+                    block_id: empty_block_id(),
+                    block: None,
+                })
+            }
+        };
+        let txs = node.get_block_txs(block.signed_header.header.height);
+        Ok(BlockByHashResponse {
+            block_id: block_id(hash),
+            block: Some(to_full_block(block, txs)),
+        })
+    }
+
+    /// JsonRPC /header endpoint.
+    fn header(req: HeaderRequest, state: Self) -> JrpcResult<HeaderResponse> {
+        log!(Log::Jrpc, "/header     {:?}", req);
+        let node = state.node.read();
+        let block = get_block(&node, req.height)?;
+        Ok(HeaderResponse {
+            header: block.signed_header.header,
+        })
+    }
+
+    /// JsonRPC /header_by_hash endpoint.
+    fn header_by_hash(req: ByHashRequest, state: Self) -> JrpcResult<HeaderByHashResponse> {
+        log!(Log::Jrpc, "/header_by_hash {:?}", req);
+        let hash = parse_block_hash(&req.hash).ok_or(JrpcError::WrongParameters)?;
+        let node = state.node.read();
+        // Like Tendermint, unknown hashes are not an error
+        let header = node
+            .chain()
+            .get_block_by_hash(&hash)
+            .map(|block| block.signed_header.header);
+        Ok(HeaderByHashResponse { header })
+    }
+
+    /// JsonRPC /blockchain endpoint.
+    fn blockchain(req: BlockchainRequest, state: Self) -> JrpcResult<BlockchainResponse> {
+        log!(Log::Jrpc, "/blockchain {:?}", req);
+        let node = state.node.read();
+        let last_height = node.chain().get_height().revision_height;
+        // Return at most 20 blocks, ending at the highest requested height
+        let max_height = match req.max_height {
+            None => last_height,
+            Some(height) => std::cmp::min(height.value(), last_height),
+        };
+        let min_height = match req.min_height {
+            None => 1,
+            Some(height) => std::cmp::max(height.value(), 1),
+        };
+        let min_height = std::cmp::max(
+            min_height,
+            max_height.saturating_sub(MAX_BLOCKCHAIN_BLOCKS - 1),
+        );
+        if min_height > max_height {
+            return Err(JrpcError::InternalError(format!(
+                "min height {} can't be greater than max height {}",
+                min_height, max_height
+            )));
+        }
+
+        let block_metas = (min_height..=max_height)
+            .rev()
+            .filter_map(|height| node.chain().get_block(height))
+            .map(|block| {
+                let header = block.signed_header.header.clone();
+                let txs = node.get_block_txs(header.height);
+                let num_txs = txs.len() as u64;
+                let block_size = to_full_block(block, txs).encoded_len() as u64;
+                block::Meta {
+                    block_id: block_id(header.hash()),
+                    block_size,
+                    header,
+                    num_txs,
+                }
+            })
+            .collect();
+        Ok(BlockchainResponse {
+            last_height: (last_height as u32).into(),
+            block_metas,
+        })
+    }
+
     /// JsonRPC /commit endpoint.
     fn commit(req: CommitRequest, state: Self) -> JrpcResult<CommitResponse> {
         log!(Log::Jrpc, "/commit     {:?}", req);
-        let height = match req.height {
-            None => 0,
-            Some(height) => height.into(),
-        };
         let node = state.node.read();
-        let block = node
-            .chain()
-            .get_block(height)
-            .ok_or(JrpcError::InvalidRequest)?;
+        let block = get_block(&node, req.height)?;
+        // The commit of a block is canonical once it is included in the next block, the commit
+        // of the latest block is only the one seen by the node
+        let height = block.signed_header.header.height.value();
+        let canonical = height < node.chain().get_height().revision_height;
         let signed_header = block.signed_header;
         Ok(CommitResponse {
             signed_header,
-            canonical: false,
+            canonical,
         })
     }
 
@@ -119,7 +347,7 @@ where
         let genesis_block = node.chain().get_block(1).unwrap();
         let genesis = tendermint::Genesis {
             genesis_time: genesis_block.signed_header.header.time,
-            chain_id: node.chain_id().clone(),
+            chain_id: node.chain_id(),
             consensus_params: node.consensus_params().clone(),
             validators: genesis_block.validators.validators().clone(),
             app_hash: vec![100, 200],
@@ -132,17 +360,15 @@ where
     fn validators(req: ValidatorsRequest, state: Self) -> JrpcResult<ValidatorResponse> {
         log!(Log::Jrpc, "/validators {:?}", req);
         let node = state.node.read();
-        let block = node
-            .chain()
-            .get_block(req.height.unwrap().into())
-            .ok_or(JrpcError::InvalidRequest)?;
-        let validators = block.validators.validators().clone();
-        let total = validators.len() as i32;
+        let block = get_block(&node, req.height)?;
+        let validators = block.validators.validators();
+        let total = validators.len();
+        let range = paginate(req.page, req.per_page, total)?;
 
         Ok(ValidatorResponse::new(
             block.signed_header.header.height,
-            validators,
-            total,
+            validators[range].to_vec(),
+            total as i32,
         ))
     }
 
@@ -151,21 +377,10 @@ where
     fn status(req: StatusRequest, state: Self) -> JrpcResult<StatusResponse> {
         log!(Log::Jrpc, "/status     {:?}", req);
         let node = state.node.read();
-        let node_info = node.info().clone();
-        let sync_info = node.get_sync_info();
-        let validator_info = tendermint::validator::Info {
-            address: tendermint::account::Id::new([41; 20]),
-            pub_key: tendermint::public_key::PublicKey::from_raw_ed25519(
-                &hex::decode(PUBLICK_KEY).unwrap(),
-            )
-            .unwrap(),
-            voting_power: (1_u32).into(),
-            proposer_priority: 1.into(),
-        };
         Ok(StatusResponse {
-            node_info,
-            sync_info,
-            validator_info,
+            node_info: node.info(),
+            sync_info: node.get_sync_info(),
+            validator_info: node.get_validator_info(),
         })
     }
 
@@ -197,7 +412,7 @@ where
     /// JsonRPC /broadcast_tx_commit endpoint.
     fn broadcast_tx_commit(
         req: BroadcastTxCommitRequest,
-        mut state: Self,
+        state: Self,
     ) -> JrpcResult<BroadcastTxCommitResponse> {
         log!(
             Log::Jrpc,
@@ -205,55 +420,330 @@ where
             req.tx.as_bytes().len()
         );
 
-        // Decode the txs
-        let data: Vec<u8> = req.tx.into();
-        let tx_raw = TxRaw::decode(&*data).map_err(|_| JrpcError::InvalidRequest)?;
-        let tx_body = TxBody::decode(&*tx_raw.body_bytes).map_err(|_| JrpcError::InvalidRequest)?;
-
-        // Deliver the txs
-        let ibc_events = deliver(&mut state.node, tx_body.messages).map_err(|e| {
-            log!(Log::Jrpc, "deliver error: '{}'", e);
-            JrpcError::ServerError
-        })?;
-
-        // Transform `IBCEvent` into `abci::Event`
-        // TODO: This is a workaround for https://github.com/informalsystems/ibc-rs/issues/838
-        let events = ibc_events
-            .iter()
-            .filter_map(|e| match e {
-                IbcEvent::CreateClient(c) => Some(Event {
-                    type_str: "create_client".to_string(),
-                    attributes: vec![Tag {
-                        key: "client_id".parse().unwrap(),
-                        value: c.client_id().to_string().parse().unwrap(),
-                    }],
-                }),
-                _ => None,
-            })
-            .collect();
+        let committed =
+            tx::broadcast_tx_commit(&state.node, req.tx).map_err(JrpcError::InternalError)?;
+        Ok(BroadcastTxCommitResponse {
+            check_tx: committed.check_tx,
+            deliver_tx: committed.deliver_tx,
+            hash: committed.hash,
+            height: committed.height,
+        })
+    }
+
+    /// JsonRPC /tx endpoint.
+    fn tx(req: TxRequest, state: Self) -> JrpcResult<TxResponse> {
+        log!(Log::Jrpc, "/tx         {:?}", req);
+        let hash = subtle_encoding::base64::decode(&req.hash)
+            .ok()
+            .and_then(|bytes| <[u8; HASH_LENGTH]>::try_from(bytes.as_slice()).ok())
+            .map(Hash::new)
+            .ok_or(JrpcError::WrongParameters)?;
+        let node = state.node.read();
+        let indexed = node
+            .get_tx(&hash)
+            .ok_or_else(|| JrpcError::InternalError(format!("tx ({}) not found", hash)))?;
+        Ok(TxResponse {
+            hash,
+            height: indexed.height,
+            index: indexed.index,
+            tx_result: indexed.result.clone(),
+            tx: indexed.tx.clone(),
+        })
+    }
+
+    /// Admin method producing `count` new blocks immediately, which fails if the chain is halted
+    /// or stalled.
+    fn admin_produce_blocks(req: ProduceBlocksRequest, state: Self) -> JrpcResult<GrowthResponse> {
+        log!(Log::Jrpc, "/admin_produce_blocks {:?}", req);
+        for _ in 0..req.count {
+            if !state.node.grow() {
+                let growth = state.node.read().growth().clone();
+                let reason = growth.check_growth().err().unwrap_or_default();
+                return Err(JrpcError::InternalError(reason));
+            }
+        }
+        Ok(growth_response(&state.node.read()))
+    }
+
+    /// Admin method pausing the automatic growth of the chain.
+    #[allow(clippy::unnecessary_wraps)]
+    fn admin_pause_growth(req: EmptyRequest, state: Self) -> JrpcResult<GrowthResponse> {
+        log!(Log::Jrpc, "/admin_pause_growth {:?}", req);
+        let node = state.node.read();
+        node.growth().pause();
+        Ok(growth_response(&node))
+    }
+
+    /// Admin method resuming the automatic growth of the chain.
+    #[allow(clippy::unnecessary_wraps)]
+    fn admin_resume_growth(req: EmptyRequest, state: Self) -> JrpcResult<GrowthResponse> {
+        log!(Log::Jrpc, "/admin_resume_growth {:?}", req);
+        let node = state.node.read();
+        node.growth().resume();
+        Ok(growth_response(&node))
+    }
+
+    /// Admin method stopping the automatic growth of the chain for a given duration.
+    #[allow(clippy::unnecessary_wraps)]
+    fn admin_stall(req: StallRequest, state: Self) -> JrpcResult<GrowthResponse> {
+        log!(Log::Jrpc, "/admin_stall {:?}", req);
+        let node = state.node.read();
+        node.growth()
+            .stall(std::time::Duration::from_millis(req.duration_ms));
+        Ok(growth_response(&node))
+    }
 
-        // Grow chain
-        let node = state.node.write();
-        node.chain().grow();
-        let block = node.chain().get_block(0).unwrap();
-        drop(node); // Release write lock
-
-        // Build a response, for now with arbitrary values.
-        let tx_result = TxResult {
-            code: Code::Ok,
-            data: None,
-            log: "Success".into(),
-            codespace: Codespace::default(),
-            gas_used: 10.into(),
-            gas_wanted: 10.into(),
-            info: Info::default(),
-            events,
+    /// Admin method returning a signed header conflicting with the one at a given height.
+    fn admin_conflicting_header(
+        req: ConflictingHeaderRequest,
+        state: Self,
+    ) -> JrpcResult<ConflictingHeaderResponse> {
+        log!(Log::Jrpc, "/admin_conflicting_header {:?}", req);
+        let node = state.node.read();
+        let height = req.height.value();
+        let app_hash = match &req.app_hash {
+            Some(app_hash) => Some(hex::decode(app_hash).map_err(|_| JrpcError::WrongParameters)?),
+            None => None,
         };
-        Ok(BroadcastTxCommitResponse {
-            check_tx: tx_result.clone(),
-            deliver_tx: tx_result,
-            hash: Hash::new([61; HASH_LENGTH]),
-            height: block.signed_header.header.height,
+        let block = node
+            .chain()
+            .get_conflicting_block(height, app_hash)
+            .ok_or_else(|| {
+                JrpcError::InternalError(format!("no validated block at height {}", height))
+            })?;
+        Ok(ConflictingHeaderResponse {
+            signed_header: block.signed_header,
+            validators: block.validators,
+            next_validators: block.next_validators,
         })
     }
+
+    /// Admin method waiting until the chain reaches a given height.
+    ///
+    /// The handler blocks a thread of the blocking pool (not the connection nor the async
+    /// runtime), the node lock is not held while waiting.
+    fn admin_wait_for_height(req: WaitForHeightRequest, state: Self) -> JrpcResult<GrowthResponse> {
+        log!(Log::Jrpc, "/admin_wait_for_height {:?}", req);
+        let growth = state.node.read().growth().clone();
+        let timeout = std::time::Duration::from_millis(req.timeout_ms);
+        if !growth.wait_for_height(req.height, timeout) {
+            return Err(JrpcError::InternalError(format!(
+                "timed out waiting for height {}, current height is {}",
+                req.height,
+                growth.height()
+            )));
+        }
+        Ok(growth_response(&state.node.read()))
+    }
+}
+
+/// Returns the growth state of the node, the height counts the blocks of all revisions.
+fn growth_response<S: store::Storage>(node: &node::Node<S>) -> GrowthResponse {
+    let height = node.chain().get_absolute_height();
+    GrowthResponse {
+        height: (height as u32).into(),
+        paused: node.growth().is_paused(),
+        catching_up: node.growth().is_catching_up(),
+    }
+}
+
+fn default_wait_timeout_ms() -> u64 {
+    10_000
+}
+
+/// Returns the block at `height`, or the latest block if omitted.
+fn get_block<S: store::Storage>(
+    node: &node::Node<S>,
+    height: Option<block::Height>,
+) -> JrpcResult<TmLightBlock> {
+    let height = node
+        .chain()
+        .resolve_height(height.map(|h| h.value()))
+        .map_err(JrpcError::InternalError)?;
+    node.chain().get_block(height).ok_or(JrpcError::ServerError)
+}
+
+/// Returns the ID of a block, blocks are made of a single part.
+fn block_id(hash: tendermint::Hash) -> block::Id {
+    block::Id {
+        part_set_header: block::parts::Header::new(1, hash).unwrap(),
+        hash,
+    }
+}
+
+/// The id reported for unknown blocks.
+fn empty_block_id() -> block::Id {
+    block::Id {
+        part_set_header: block::parts::Header::new(0, tendermint::Hash::None).unwrap(),
+        hash: tendermint::Hash::None,
+    }
+}
+
+/// Parse a block hash, encoded either in hex (as Tendermint prints them) or in base64 (as
+/// Tendermint encodes bytes in JSON).
+fn parse_block_hash(hash: &str) -> Option<tendermint::Hash> {
+    let hex_hash = hash.trim_start_matches("0x");
+    let bytes = if hex_hash.len() == 2 * HASH_LENGTH {
+        hex::decode(hex_hash).ok()?
+    } else {
+        subtle_encoding::base64::decode(hash).ok()?
+    };
+    tendermint::Hash::from_bytes(tendermint::hash::Algorithm::Sha256, &bytes).ok()
+}
+
+/// Returns the range of the items of the requested page, following Tendermint's semantics: the
+/// number of items per page defaults to 30 and is capped to 100, and the page must exist.
+fn paginate(
+    page: Option<usize>,
+    per_page: Option<usize>,
+    total: usize,
+) -> JrpcResult<std::ops::Range<usize>> {
+    let per_page = match per_page {
+        None | Some(0) => DEFAULT_PER_PAGE,
+        Some(per_page) => std::cmp::min(per_page, MAX_PER_PAGE),
+    };
+    let pages = std::cmp::max((total + per_page - 1) / per_page, 1);
+    let page = page.unwrap_or(1);
+    if page == 0 || page > pages {
+        return Err(JrpcError::InternalError(format!(
+            "page should be within [1, {}] range, given {}",
+            pages, page
+        )));
+    }
+    let start = (page - 1) * per_page;
+    let end = std::cmp::min(start + per_page, total);
+    Ok(start..end)
+}
+
+/// Deserialize an optional integer, encoded either as a JSON number or a string as Tendermint
+/// does.
+fn optional_usize<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::Number(n)) => n
+            .as_u64()
+            .map(|n| Some(n as usize))
+            .ok_or_else(|| D::Error::custom("expected a positive integer")),
+        Some(serde_json::Value::String(s)) => s.parse().map(Some).map_err(D::Error::custom),
+        Some(_) => Err(D::Error::custom("expected an integer")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::node::Node;
+
+    #[test]
+    fn blockchain() {
+        let node = Node::new(&Config::default()).shared();
+        let tx = Transaction::new(vec![1; 100]);
+        let txs = vec![(Hash::new([1; 32]), tx, tx::empty_result())];
+        assert!(node.write().grow_with_txs(txs));
+        assert!(node.grow());
+        let request = BlockchainRequest {
+            min_height: None,
+            max_height: None,
+        };
+        let response = Jrpc::blockchain(request, Jrpc { node }).unwrap();
+
+        // The metas are ordered from the highest height, the block at height 2 has the transaction
+        let metas = response.block_metas;
+        assert_eq!(metas.len(), 3);
+        assert_eq!(metas[1].header.height.value(), 2);
+        assert_eq!(metas[1].num_txs, 1);
+        assert_eq!(metas[0].num_txs, 0);
+        assert!(metas[1].block_size > metas[0].block_size);
+    }
+
+    #[test]
+    fn commit() {
+        let node = Node::new(&Config::default()).shared();
+        assert!(node.grow());
+        let latest = node.read().chain().get_height().revision_height;
+        let commit = |height: u64| {
+            let request = CommitRequest {
+                height: Some(block::Height::from(height as u32)),
+            };
+            Jrpc::commit(request, Jrpc { node: node.clone() }).unwrap()
+        };
+        assert!(commit(latest - 1).canonical);
+        assert!(!commit(latest).canonical);
+    }
+
+    #[test]
+    fn unknown_tx() {
+        let node = Node::new(&Config::default()).shared();
+        let hash = Hash::new([0; 32]);
+        let request = TxRequest {
+            hash: String::from_utf8(subtle_encoding::base64::encode(hash.as_bytes())).unwrap(),
+            prove: false,
+        };
+        match Jrpc::tx(request, Jrpc { node }) {
+            Err(JrpcError::InternalError(message)) => {
+                assert_eq!(message, format!("tx ({}) not found", hash))
+            }
+            _ => panic!("Unknown transactions must not be found"),
+        }
+    }
+
+    #[test]
+    fn by_hash() {
+        let node = Node::new(&Config::default()).shared();
+        let hash = node
+            .read()
+            .chain()
+            .get_block(1)
+            .unwrap()
+            .signed_header
+            .header
+            .hash();
+        let request = |hash: tendermint::Hash| ByHashRequest {
+            hash: hash.to_string(),
+        };
+
+        let response = Jrpc::block_by_hash(request(hash), Jrpc { node: node.clone() }).unwrap();
+        assert_eq!(response.block_id.hash, hash);
+        assert_eq!(response.block.unwrap().header.height.value(), 1);
+        let response = Jrpc::header_by_hash(request(hash), Jrpc { node: node.clone() }).unwrap();
+        assert_eq!(response.header.unwrap().hash(), hash);
+
+        // Unknown hashes give a null block or header
+        let unknown = tendermint::Hash::Sha256([0; 32]);
+        let response = Jrpc::block_by_hash(request(unknown), Jrpc { node: node.clone() }).unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert!(response["block"].is_null());
+        assert_eq!(response["block_id"]["hash"], "");
+        let response = Jrpc::header_by_hash(request(unknown), Jrpc { node }).unwrap();
+        assert!(response.header.is_none());
+    }
+
+    #[test]
+    fn pagination() {
+        assert_eq!(paginate(None, None, 3).unwrap(), 0..3);
+        assert_eq!(paginate(Some(2), Some(2), 3).unwrap(), 2..3);
+        assert_eq!(paginate(Some(1), Some(0), 3).unwrap(), 0..3);
+        assert_eq!(paginate(Some(2), Some(1000), 250).unwrap(), 100..200);
+        assert_eq!(paginate(Some(1), None, 0).unwrap(), 0..0);
+        assert!(paginate(Some(3), Some(2), 3).is_err());
+        assert!(paginate(Some(0), None, 3).is_err());
+    }
+
+    #[test]
+    fn block_hash() {
+        let hex_hash = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        let hash = parse_block_hash(hex_hash).unwrap();
+        assert_eq!(hash.to_string(), hex_hash);
+        assert_eq!(parse_block_hash(&hex_hash.to_lowercase()), Some(hash));
+        assert_eq!(parse_block_hash(&format!("0x{}", hex_hash)), Some(hash));
+        let base64_hash = String::from_utf8(subtle_encoding::base64::encode(hash.as_bytes()));
+        assert_eq!(parse_block_hash(&base64_hash.unwrap()), Some(hash));
+        assert_eq!(parse_block_hash("not a hash"), None);
+    }
 }
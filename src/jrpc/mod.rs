@@ -24,8 +24,9 @@ pub async fn serve<S: 'static + Storage + Sync + Send>(
     node: node::SharedNode<S>,
     addr: std::net::SocketAddr,
 ) -> Result<(), std::convert::Infallible> {
-    let jrpc_api = warp::path::end().and(Jrpc::new_mimic(node));
-    let ws = warp::path(WEBSOCKET_PATH).and(Ws::new_mimic());
+    let methods = Jrpc::methods(node.clone());
+    let ws = warp::path(WEBSOCKET_PATH).and(Ws::new_mimic(node, methods.clone()));
+    let jrpc_api = warp::path::end().and(methods.filter());
     warp::serve(jrpc_api.or(ws))
         .run(addr)
         .then(|()| async { Ok(()) })
@@ -1,7 +1,8 @@
 //! # A JsonRPC mini-framework.
 //!
 //! This module provides a small framework for building JsonRPC API on top of a `wrap` filter, this
-//! is done through the builder methods of `JrpcFilter`.
+//! is done through the builder methods of `JrpcFilter`. The registered methods can also be served
+//! by other transports (such as websockets) through `JrpcMethods`.
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -10,21 +11,48 @@ use warp::filters;
 use warp::Filter;
 
 pub const JRPC_VERSION: &str = "2.0";
+const JRPC_CODE_PARSE_ERROR: i32 = -32700;
 const JRPC_CODE_METHOD_NOT_FOUND: i32 = -32601;
 const JRPC_CODE_INVALID_PARAMS: i32 = -32605;
 const JRPC_CODE_INVALID_REQUEST: i32 = -32600;
+const JRPC_CODE_INTERNAL_ERROR: i32 = -32603;
 const JRPC_CODE_SERVER_ERROR: i32 = -32000;
 
 /// JsonRPC envelope.
+///
+/// The id (a string, a number or null) is echoed back verbatim in the response, requests without
+/// id are notifications, which get no response. Missing params are interpreted as null.
 #[derive(Deserialize, Clone, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct JrpcEnvelope {
     pub jsonrpc: String,
-    pub id: String,
+    #[serde(default, deserialize_with = "deserialize_id")]
+    pub id: Option<Value>,
     pub method: String,
+    #[serde(default)]
     pub params: Value,
 }
 
+impl JrpcEnvelope {
+    /// Whether the request is a notification, that is a request without id (a null id is not
+    /// missing).
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+
+    /// The id of the response to the request, null for notifications.
+    pub fn response_id(&self) -> Value {
+        self.id.clone().unwrap_or(Value::Null)
+    }
+}
+
+/// Deserializes a present id, including null, as `Some`: only missing ids are `None`.
+fn deserialize_id<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
 /// JsonRPC context.
 #[derive(Debug)]
 struct JrpcCtx {
@@ -42,6 +70,10 @@ pub enum JrpcError {
     WrongVersion,
     ServerError,
     InvalidRequest,
+    /// The request is not valid JSON.
+    ParseError,
+    /// An error raised while handling a request, the message is returned in the error's data.
+    InternalError(String),
 }
 
 /// JsonRPC error details.
@@ -49,13 +81,15 @@ pub enum JrpcError {
 pub struct JrpcErrorDetails {
     code: i32,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
 }
 
 /// JsonRPC response.
 #[derive(Serialize)]
 pub struct JrpcResponse<T> {
     pub jsonrpc: String,
-    pub id: String,
+    pub id: Value,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<T>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -64,11 +98,10 @@ pub struct JrpcResponse<T> {
 
 pub type JrpcResult<T> = Result<T, JrpcError>;
 type MethodMap<S> = HashMap<String, Box<dyn Fn(JrpcEnvelope, S) -> String + Send + Sync>>;
-type SharedMethodMap<S> =
-    std::sync::Arc<HashMap<String, Box<dyn Fn(JrpcEnvelope, S) -> String + Send + Sync>>>;
+type SharedMethodMap<S> = std::sync::Arc<MethodMap<S>>;
 
 /// A JsonRPC `warp` filter, methods can be added through the builder method
-/// `add` while the filter is built with the `build` method.
+/// `add` while the filter is built with the `filter` method of `JrpcMethods`.
 ///
 /// `warp` is an HTTP framework that enable building API from 'filters' composition,
 /// check its documentation for more informations.
@@ -86,24 +119,12 @@ impl<S: Send + Sync + Clone> JrpcFilter<S> {
         }
     }
 
-    /// Build a `wrap` filter that handles JRPC requests.
-    /// To register methods use the `add` method on `JrpcFilter`.
-    pub fn build(self) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
-        filters::body::json::<JrpcEnvelope>()
-            .and(Self::with_methods(self.methods))
-            .and(Self::with_state(self.shared_state))
-            .and_then(
-                |ctx: JrpcEnvelope, methods: SharedMethodMap<S>, state: S| async move {
-                    if ctx.jsonrpc != JRPC_VERSION {
-                        return Self::build_error(ctx.id, JrpcError::WrongVersion);
-                    }
-                    if let Some(method) = methods.get(&ctx.method) {
-                        Ok(method(ctx, state))
-                    } else {
-                        Self::build_error(ctx.id, JrpcError::WrongMethod)
-                    }
-                },
-            )
+    /// Returns the registered methods, which can be shared by several transports.
+    pub fn into_methods(self) -> JrpcMethods<S> {
+        JrpcMethods {
+            methods: std::sync::Arc::new(self.methods),
+            shared_state: self.shared_state,
+        }
     }
 
     /// A builder method to register new JsonRPC methods.
@@ -122,83 +143,354 @@ impl<S: Send + Sync + Clone> JrpcFilter<S> {
         R: Serialize,
     {
         let method_handler = move |ctx: JrpcEnvelope, state: S| {
-            let result = match serde_json::from_value::<T>(ctx.params) {
-                Ok(params) => handler(params, state),
-                Err(_) => Err(JrpcError::WrongParameters),
-            };
-            let response = Self::build_response(ctx.id, result);
+            let result = parse_params::<T>(ctx.params).and_then(|params| handler(params, state));
+            let response = build_response(ctx.response_id(), result);
             serde_json::to_string(&response).unwrap()
         };
         self.methods
             .insert(method.to_string(), Box::new(method_handler));
         self
     }
+}
 
-    /// Build the JsonRPC response from a JsonRPC Result and a request ID.
-    fn build_response<R: Serialize>(id: String, res: JrpcResult<R>) -> JrpcResponse<R> {
-        match res {
-            Ok(value) => JrpcResponse {
-                id,
-                jsonrpc: JRPC_VERSION.to_string(),
-                result: Some(value),
-                error: None,
-            },
-            Err(err) => JrpcResponse {
-                id,
-                jsonrpc: JRPC_VERSION.to_string(),
-                result: None,
-                error: Some(err.into()),
-            },
+/// The JsonRPC methods registered through a `JrpcFilter`, along with their shared state.
+pub struct JrpcMethods<S> {
+    methods: SharedMethodMap<S>,
+    shared_state: S,
+}
+
+impl<S: Clone> Clone for JrpcMethods<S> {
+    fn clone(&self) -> Self {
+        JrpcMethods {
+            methods: std::sync::Arc::clone(&self.methods),
+            shared_state: self.shared_state.clone(),
+        }
+    }
+}
+
+impl<S: 'static + Send + Sync + Clone> JrpcMethods<S> {
+    /// Dispatch a request to the method handler and return the serialized response.
+    pub fn handle(&self, ctx: JrpcEnvelope) -> String {
+        if ctx.jsonrpc != JRPC_VERSION {
+            return build_error(ctx.response_id(), JrpcError::WrongVersion);
+        }
+        if let Some(method) = self.methods.get(&ctx.method) {
+            method(ctx, self.shared_state.clone())
+        } else {
+            build_error(ctx.response_id(), JrpcError::WrongMethod)
         }
     }
 
-    /// Build an error response from a given JrpcError.
-    #[allow(clippy::unnecessary_wraps)]
-    fn build_error(id: String, error: JrpcError) -> Result<String, std::convert::Infallible> {
-        let response = Self::build_response::<()>(id, Err(error));
-        Ok(serde_json::to_string(&response).unwrap())
+    /// Dispatch a request on a blocking thread of the runtime, as the method handlers may block
+    /// (e.g. while waiting for a given height), and return the serialized response.
+    pub async fn handle_blocking(&self, ctx: JrpcEnvelope) -> String {
+        let methods = self.clone();
+        let id = ctx.response_id();
+        tokio::task::spawn_blocking(move || methods.handle(ctx))
+            .await
+            .unwrap_or_else(|_| build_error(id, JrpcError::ServerError))
     }
 
-    /// A helper function that returns a filter extracting the methods.
-    fn with_methods(
-        methods: MethodMap<S>,
-    ) -> impl Filter<Extract = (SharedMethodMap<S>,), Error = core::convert::Infallible> + Clone
-    {
-        let shared_methods = std::sync::Arc::new(methods);
-        filters::any::any().map(move || std::sync::Arc::clone(&shared_methods))
+    /// Dispatch a single or batch request, the responses of a batch are returned as an array, in
+    /// the order of the requests. Returns `None` if there is no response, as notifications get
+    /// none.
+    fn handle_request(&self, body: &[u8]) -> Option<String> {
+        let (requests, is_batch) = match parse_batch(body) {
+            Ok(requests) => requests,
+            Err(response) => return Some(response),
+        };
+        let responses = requests
+            .into_iter()
+            .map(|request| match request {
+                Ok(ctx) => {
+                    let is_notification = ctx.is_notification();
+                    Some(self.handle(ctx)).filter(|_| !is_notification)
+                }
+                Err(response) => Some(response),
+            })
+            .collect();
+        join_responses(responses, is_batch)
     }
 
-    /// A helper function that returns a filter extracting the shared state.
-    fn with_state(
-        shared_state: S,
-    ) -> impl Filter<Extract = (S,), Error = core::convert::Infallible> + Clone {
-        filters::any::any().map(move || shared_state.clone())
+    /// Build a `wrap` filter that handles JRPC requests, including batches. The body of the
+    /// response is empty if there is no response to send, that is for notifications.
+    ///
+    /// Requests are handled on the blocking threads of the runtime, as the method handlers may
+    /// block.
+    pub fn filter(self) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+        filters::body::bytes()
+            .and(filters::any::any().map(move || self.clone()))
+            .and_then(|body: warp::hyper::body::Bytes, methods: Self| async move {
+                let response = tokio::task::spawn_blocking(move || {
+                    methods.handle_request(&body).unwrap_or_default()
+                })
+                .await
+                .unwrap_or_else(|_| build_error(Value::Null, JrpcError::ServerError));
+                Ok::<_, std::convert::Infallible>(response)
+            })
     }
 }
 
+/// Parses a single or batch request, along with whether it is a batch. Each request is parsed
+/// with `parse_envelope`, and the serialized error response is returned if the body is not valid
+/// JSON or is an empty batch.
+pub fn parse_batch(body: &[u8]) -> Result<(Vec<Result<JrpcEnvelope, String>>, bool), String> {
+    let request = match serde_json::from_slice::<Value>(body) {
+        Ok(request) => request,
+        Err(_) => return Err(build_error(Value::Null, JrpcError::ParseError)),
+    };
+    match request {
+        Value::Array(batch) if batch.is_empty() => {
+            Err(build_error(Value::Null, JrpcError::InvalidRequest))
+        }
+        Value::Array(batch) => Ok((batch.into_iter().map(parse_envelope).collect(), true)),
+        request => Ok((vec![parse_envelope(request)], false)),
+    }
+}
+
+/// Joins the serialized responses to a single or batch request, the responses of a batch are
+/// returned as an array in the order of the requests. The notifications have no response (`None`)
+/// and are omitted, `None` is returned if no response is left.
+pub fn join_responses(responses: Vec<Option<String>>, is_batch: bool) -> Option<String> {
+    let responses: Vec<String> = responses.into_iter().flatten().collect();
+    if responses.is_empty() {
+        None
+    } else if is_batch {
+        Some(format!("[{}]", responses.join(",")))
+    } else {
+        Some(responses.join(""))
+    }
+}
+
+/// Parses a JsonRPC request, returns the serialized error response if the request is invalid.
+pub fn parse_envelope(request: Value) -> Result<JrpcEnvelope, String> {
+    // The id is echoed back in the error response, if valid
+    let id = match request.get("id") {
+        Some(id) if id.is_string() || id.is_number() => id.clone(),
+        _ => Value::Null,
+    };
+    match serde_json::from_value::<JrpcEnvelope>(request) {
+        Ok(ctx) if ctx.id.as_ref().map_or(true, is_valid_id) => Ok(ctx),
+        _ => Err(build_error(id, JrpcError::InvalidRequest)),
+    }
+}
+
+/// Whether `id` is a valid request id: a string, a number or null.
+fn is_valid_id(id: &Value) -> bool {
+    id.is_string() || id.is_number() || id.is_null()
+}
+
+/// Parses the parameters of a request, missing (or null) parameters are interpreted as an empty
+/// object by the methods that expect one.
+pub fn parse_params<T: DeserializeOwned>(params: Value) -> JrpcResult<T> {
+    let result = match params {
+        Value::Null => serde_json::from_value(Value::Null)
+            .or_else(|_| serde_json::from_value(Value::Object(Default::default()))),
+        params => serde_json::from_value(params),
+    };
+    result.map_err(|_| JrpcError::WrongParameters)
+}
+
+/// Build the JsonRPC response from a JsonRPC Result and a request ID.
+fn build_response<R: Serialize>(id: Value, res: JrpcResult<R>) -> JrpcResponse<R> {
+    match res {
+        Ok(value) => JrpcResponse {
+            id,
+            jsonrpc: JRPC_VERSION.to_string(),
+            result: Some(value),
+            error: None,
+        },
+        Err(err) => JrpcResponse {
+            id,
+            jsonrpc: JRPC_VERSION.to_string(),
+            result: None,
+            error: Some(err.into()),
+        },
+    }
+}
+
+/// Build an error response from a given JrpcError.
+pub fn build_error(id: Value, error: JrpcError) -> String {
+    let response = build_response::<()>(id, Err(error));
+    serde_json::to_string(&response).unwrap()
+}
+
 impl From<JrpcError> for JrpcErrorDetails {
     fn from(err: JrpcError) -> Self {
         match err {
             JrpcError::WrongMethod => JrpcErrorDetails {
                 code: JRPC_CODE_METHOD_NOT_FOUND,
                 message: "Method does not exsists".to_string(),
+                data: None,
             },
             JrpcError::WrongParameters => JrpcErrorDetails {
                 code: JRPC_CODE_INVALID_PARAMS,
                 message: "Invalid parameters".to_string(),
+                data: None,
             },
             JrpcError::WrongVersion => JrpcErrorDetails {
                 code: JRPC_CODE_INVALID_REQUEST,
                 message: "Invalid jsonrpc version, expected '2.0'".to_string(),
+                data: None,
             },
             JrpcError::ServerError => JrpcErrorDetails {
                 code: JRPC_CODE_SERVER_ERROR,
                 message: "Server error".to_string(),
+                data: None,
             },
             JrpcError::InvalidRequest => JrpcErrorDetails {
                 code: JRPC_CODE_INVALID_REQUEST,
                 message: "Invalid request".to_string(),
+                data: None,
+            },
+            JrpcError::ParseError => JrpcErrorDetails {
+                code: JRPC_CODE_PARSE_ERROR,
+                message: "Parse error".to_string(),
+                data: None,
+            },
+            JrpcError::InternalError(data) => JrpcErrorDetails {
+                code: JRPC_CODE_INTERNAL_ERROR,
+                message: "Internal error".to_string(),
+                data: Some(data),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct HeightParams {
+        height: Option<u64>,
+    }
+
+    async fn call(body: &str) -> Value {
+        serde_json::from_str(&post(body).await).unwrap()
+    }
+
+    /// Post a request, returns the body of the response.
+    async fn post(body: &str) -> String {
+        let filter = JrpcFilter::new(())
+            .add("echo", |params: Value, _: ()| Ok(params))
+            .add("ping", |_: (), _: ()| Ok("pong"))
+            .add("height", |params: HeightParams, _: ()| Ok(params.height))
+            .into_methods()
+            .filter();
+        let response = warp::test::request()
+            .method("POST")
+            .body(body)
+            .reply(&filter)
+            .await;
+        String::from(std::str::from_utf8(response.body()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn blocking_handlers() {
+        // Both requests must be handled at the same time to get through the barrier, even though
+        // the runtime has a single thread
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let filter = JrpcFilter::new(barrier)
+            .add(
+                "wait",
+                |_: (), barrier: std::sync::Arc<std::sync::Barrier>| {
+                    barrier.wait();
+                    Ok(())
+                },
+            )
+            .into_methods()
+            .filter();
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": "wait" }).to_string();
+        let request = || {
+            warp::test::request()
+                .method("POST")
+                .body(body.clone())
+                .reply(&filter)
+        };
+        let (first, second) = futures::join!(request(), request());
+        assert_eq!(first.status(), 200);
+        assert_eq!(second.status(), 200);
+    }
+
+    fn request(id: &str, method: &str) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": [id] })
+    }
+
+    #[tokio::test]
+    async fn batch() {
+        let response = call(&request("1", "echo").to_string()).await;
+        assert_eq!(response["result"], json!(["1"]));
+
+        let batch = json!([
+            request("1", "echo"),
+            request("2", "missing"),
+            { "jsonrpc": "2.0", "id": "3" },
+            request("4", "echo"),
+        ]);
+        let responses = call(&batch.to_string()).await;
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0]["result"], json!(["1"]));
+        assert_eq!(responses[1]["id"], "2");
+        assert_eq!(responses[1]["error"]["code"], JRPC_CODE_METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["id"], "3");
+        assert_eq!(responses[2]["error"]["code"], JRPC_CODE_INVALID_REQUEST);
+        assert_eq!(responses[3]["result"], json!(["4"]));
+
+        let response = call("[]").await;
+        assert_eq!(response["error"]["code"], JRPC_CODE_INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn notifications() {
+        // Requests without id get no response, unlike the ones with a null id
+        let notification = json!({ "jsonrpc": "2.0", "method": "ping" });
+        assert_eq!(post(&notification.to_string()).await, "");
+        let missing = json!({ "jsonrpc": "2.0", "method": "missing" });
+        assert_eq!(post(&missing.to_string()).await, "");
+        let null_id = json!({ "jsonrpc": "2.0", "id": null, "method": "ping" });
+        assert_eq!(call(&null_id.to_string()).await["result"], "pong");
+
+        // Notifications are omitted from the batch responses
+        let batch = json!([notification, request("1", "echo"), missing]);
+        let responses = call(&batch.to_string()).await;
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0]["id"], "1");
+        assert_eq!(post(&json!([notification, missing]).to_string()).await, "");
+
+        // Invalid requests are answered even without id
+        let response = call(&json!({ "jsonrpc": "2.0" }).to_string()).await;
+        assert_eq!(response["error"]["code"], JRPC_CODE_INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn ids_and_params() {
+        // Ids are echoed back verbatim
+        for id in &[json!("a"), json!(7), json!(Value::Null)] {
+            let request = json!({ "jsonrpc": "2.0", "id": id, "method": "ping" });
+            let response = call(&request.to_string()).await;
+            assert_eq!(&response["id"], id);
+            assert_eq!(response["result"], "pong");
+        }
+        let request = json!({ "jsonrpc": "2.0", "id": [1], "method": "ping" });
+        let response = call(&request.to_string()).await;
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], JRPC_CODE_INVALID_REQUEST);
+
+        // Missing or null params are interpreted as an empty object when needed
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "height" });
+        assert_eq!(call(&request.to_string()).await["result"], Value::Null);
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "height", "params": null });
+        assert_eq!(call(&request.to_string()).await["result"], Value::Null);
+        let request =
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "height", "params": { "height": 3 } });
+        assert_eq!(call(&request.to_string()).await["result"], 3);
+
+        let response = call("{ \"jsonrpc\": \"2.0\", ").await;
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], JRPC_CODE_PARSE_ERROR);
+    }
+}
@@ -1,84 +1,272 @@
 //! The Tendermock JsonRPC Websocket API.
-use futures::{SinkExt, StreamExt};
-use serde::Serialize;
+//!
+//! Clients can subscribe to the events of the node with Tendermint queries, the matching events
+//! are then pushed to the client over the websocket, in the Tendermint event format.
+//!
+//! The other JsonRPC methods are dispatched to the same handlers as the HTTP API.
+//!
+//! As in Tendermint, the node pings its clients periodically and disconnects the ones that stay
+//! silent longer than the read timeout, as well as the slow clients whose queue of outgoing
+//! messages is full.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{Sink, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tendermint_rpc::endpoint::subscribe::{Request, Response};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time;
 use warp::ws::{Message, WebSocket, Ws as WarpWs};
 use warp::Filter;
 
-use super::utils::{JrpcEnvelope, JrpcError, JrpcResponse, JrpcResult, JRPC_VERSION};
+use super::api::Jrpc;
+use super::utils::{
+    join_responses, parse_batch, parse_params, JrpcEnvelope, JrpcError, JrpcMethods, JrpcResponse,
+    JrpcResult, JRPC_VERSION,
+};
+use crate::events::{Event, EventData, Query};
 use crate::logger::Log;
+use crate::node;
+use crate::store::Storage;
+
+/// The maximum number of subscriptions of a single client, as in Tendermint.
+const MAX_SUBSCRIPTIONS_PER_CLIENT: usize = 5;
 
 /// A struct that can be used to build the Websocket `warp` filter, see the `new` method.
 pub struct Ws {}
 
+/// Parameters of the unsubscribe method.
+#[derive(Deserialize, Debug)]
+struct UnsubscribeRequest {
+    query: String,
+}
+
+/// Parameters of the unsubscribe_all method.
+#[derive(Deserialize, Debug)]
+struct UnsubscribeAllRequest {}
+
+/// Response of the unsubscribe methods.
+#[derive(Serialize, Debug)]
+struct UnsubscribeResponse {}
+
+/// An event matching a subscription, as pushed to the client.
+#[derive(Serialize)]
+struct EventMessage<'a> {
+    query: &'a str,
+    data: &'a EventData,
+    events: &'a BTreeMap<String, Vec<String>>,
+}
+
+/// The state of a websocket connection.
+struct Connection<S: Storage> {
+    node: node::SharedNode<S>,
+    /// The JsonRPC methods shared with the HTTP API.
+    methods: JrpcMethods<Jrpc<S>>,
+    /// Queues messages for the client.
+    sender: Sender<String>,
+    /// Notified when the client does not read its messages fast enough.
+    slow_client: Arc<Notify>,
+    /// The tasks forwarding the events of the subscriptions, indexed by query.
+    subscriptions: HashMap<String, JoinHandle<()>>,
+}
+
 impl Ws {
     /// Creates a `warp` filter that mimics the Tendermint Websocket API.
-    pub fn new_mimic() -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
-        warp::ws().map(|ws: WarpWs| ws.on_upgrade(handler)).boxed()
+    pub fn new_mimic<S: 'static + Storage + Sync + Send>(
+        node: node::SharedNode<S>,
+        methods: JrpcMethods<Jrpc<S>>,
+    ) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
+        warp::ws()
+            .map(move |ws: WarpWs| {
+                let node = node.clone();
+                let methods = methods.clone();
+                ws.on_upgrade(move |ws| handler(ws, node, methods))
+            })
+            .boxed()
     }
 }
 
 /// Handle a websocket connection.
-async fn handler(ws: WebSocket) {
+async fn handler<S: 'static + Storage + Sync + Send>(
+    ws: WebSocket,
+    node: node::SharedNode<S>,
+    methods: JrpcMethods<Jrpc<S>>,
+) {
     log!(Log::Websocket, "Connection");
-    let (mut sending_ws, mut listening_ws) = ws.split();
-    while let Some(result) = listening_ws.next().await {
+    let config = node.read().websocket_config().clone();
+    let read_timeout = Duration::from_millis(config.read_timeout_ms);
+    let (sending_ws, mut listening_ws) = ws.split();
+
+    // Responses and events are queued for the client, so that the subscriptions can push events
+    // while the connection listens to new requests.
+    let (sender, receiver) = mpsc::channel::<String>(config.write_queue_capacity);
+    let writer = tokio::spawn(write_messages(
+        sending_ws,
+        receiver,
+        Duration::from_millis(config.ping_interval_ms),
+    ));
+
+    let slow_client = Arc::new(Notify::new());
+    let mut connection = Connection {
+        node,
+        methods,
+        sender,
+        slow_client: slow_client.clone(),
+        subscriptions: HashMap::new(),
+    };
+    let mut is_slow = false;
+    loop {
+        let result = tokio::select! {
+            result = time::timeout(read_timeout, listening_ws.next()) => result,
+            _ = slow_client.notified() => {
+                is_slow = true;
+                break;
+            }
+        };
         let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
+            Ok(Some(Ok(msg))) => msg,
+            Ok(Some(Err(e))) => {
                 log!(Log::Websocket, "Receiving error: '{}'", e);
                 break;
             }
+            Ok(None) => break,
+            Err(_) => {
+                log!(Log::Websocket, "Read timeout, disconnecting");
+                break;
+            }
         };
+        if msg.is_close() {
+            break;
+        }
+        if msg.is_ping() || msg.is_pong() {
+            // Pings are answered by `warp`, and pongs only reset the read timeout
+            continue;
+        }
         let msg = if let Ok(msg) = msg.to_str() {
             msg
         } else {
             log!(Log::Websocket, "Could not interpret message as str");
             break;
         };
-        if let Err(e) = sending_ws.send(Message::text(handle_request(msg))).await {
-            log!(Log::Websocket, "Sending error: '{}'", e);
-            break;
+        let response = match handle_request(msg, &mut connection).await {
+            Some(response) => response,
+            None => continue, // Notifications get no response
         };
+        match connection.sender.try_send(response) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                is_slow = true;
+                break;
+            }
+            Err(TrySendError::Closed(_)) => break,
+        }
+    }
+
+    // Stop the subscriptions, the writer then stops once all the senders are dropped
+    let _ = unsubscribe_all(UnsubscribeAllRequest {}, &mut connection);
+    drop(connection);
+    if is_slow {
+        // The writer may be blocked by the client, the connection is dropped without closing it
+        log!(
+            Log::Websocket,
+            "Client is not reading messages fast enough, disconnecting"
+        );
+        writer.abort();
+        return;
     }
-    if let Ok(ws) = sending_ws.reunite(listening_ws) {
-        if let Err(e) = ws.close().await {
-            log!(Log::Websocket, "Closing error: '{}'", e);
+    if let Ok(sending_ws) = writer.await {
+        if let Ok(ws) = sending_ws.reunite(listening_ws) {
+            if let Err(e) = ws.close().await {
+                log!(Log::Websocket, "Closing error: '{}'", e);
+            };
         };
-    };
+    }
 }
 
-/// Parses the request, dispatch the query to the method handler and return the serialized Jrpc
-/// response.
-fn handle_request(msg: &str) -> String {
-    let req = if let Ok(req) = parse_message(msg) {
-        req
-    } else {
-        return serde_json::to_string(&JrpcResponse::<()> {
-            id: "0".to_string(),
-            jsonrpc: JRPC_VERSION.to_string(),
-            error: Some(JrpcError::InvalidRequest.into()),
-            result: None,
-        })
-        .unwrap();
+/// Send the queued messages to the client, and ping it every `ping_interval`, until the queue is
+/// closed or the client disconnects.
+async fn write_messages<W>(mut ws: W, mut receiver: Receiver<String>, ping_interval: Duration) -> W
+where
+    W: Sink<Message> + Unpin,
+    W::Error: Display,
+{
+    let mut ping = time::interval_at(time::Instant::now() + ping_interval, ping_interval);
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.recv() => match msg {
+                Some(msg) => Message::text(msg),
+                None => break,
+            },
+            _ = ping.tick() => Message::ping(Vec::new()),
+        };
+        if let Err(e) = ws.send(msg).await {
+            log!(Log::Websocket, "Sending error: '{}'", e);
+            break;
+        }
+    }
+    ws
+}
+
+/// Parses a single or batch request, dispatch the queries to the method handlers and return the
+/// serialized Jrpc response, as the HTTP API does. Returns `None` if there is no response, as
+/// notifications get none.
+async fn handle_request<S: 'static + Storage + Sync + Send>(
+    msg: &str,
+    connection: &mut Connection<S>,
+) -> Option<String> {
+    let (requests, is_batch) = match parse_batch(msg.as_bytes()) {
+        Ok(requests) => requests,
+        Err(response) => return Some(response),
     };
+    let mut responses = Vec::with_capacity(requests.len());
+    for request in requests {
+        let response = match request {
+            Ok(req) => {
+                let is_notification = req.is_notification();
+                Some(dispatch(req, connection).await).filter(|_| !is_notification)
+            }
+            Err(response) => Some(response),
+        };
+        responses.push(response);
+    }
+    join_responses(responses, is_batch)
+}
+
+/// Dispatch a query to the method handler and return the serialized Jrpc response.
+///
+/// The methods shared with the HTTP API run on a blocking thread, as they may block.
+async fn dispatch<S: 'static + Storage + Sync + Send>(
+    req: JrpcEnvelope,
+    connection: &mut Connection<S>,
+) -> String {
     if req.jsonrpc != JRPC_VERSION {
         return serialize_response::<()>(Err(JrpcError::WrongVersion), req);
     }
     match req.method.as_str() {
         "subscribe" => {
-            let res = subscribe(&req);
+            let res = subscribe(&req, connection);
+            serialize_response(res, req)
+        }
+        "unsubscribe" => {
+            let res =
+                parse_params(req.params.clone()).and_then(|params| unsubscribe(params, connection));
+            serialize_response(res, req)
+        }
+        "unsubscribe_all" => {
+            let res = parse_params(req.params.clone())
+                .and_then(|params| unsubscribe_all(params, connection));
             serialize_response(res, req)
         }
-        _ => serialize_response::<()>(Err(JrpcError::WrongMethod), req),
+        _ => connection.methods.handle_blocking(req).await,
     }
 }
 
-/// Parses the websocket message into a JsonRPC request.
-fn parse_message(msg: &str) -> JrpcResult<JrpcEnvelope> {
-    serde_json::from_str(msg).map_err(|_| JrpcError::InvalidRequest)
-}
-
 /// Serializes a JrpcResult into an actual JsonRPC response String.
 fn serialize_response<T: Serialize>(result: JrpcResult<T>, envelope: JrpcEnvelope) -> String {
     let (error, result) = match result {
@@ -86,7 +274,7 @@ fn serialize_response<T: Serialize>(result: JrpcResult<T>, envelope: JrpcEnvelop
         Err(e) => (Some(e.into()), None),
     };
     serde_json::to_string(&JrpcResponse {
-        id: envelope.id,
+        id: envelope.response_id(),
         jsonrpc: envelope.jsonrpc,
         error,
         result,
@@ -95,8 +283,313 @@ fn serialize_response<T: Serialize>(result: JrpcResult<T>, envelope: JrpcEnvelop
 }
 
 /// Handles the /subscribe endpoint.
-fn subscribe(req: &JrpcEnvelope) -> JrpcResult<Response> {
-    let _params: Request =
-        serde_json::from_value(req.params.clone()).map_err(|_| JrpcError::WrongParameters)?;
+///
+/// The events matching the query are pushed to the client with the id of the request, suffixed
+/// by `#event`, as Tendermint does.
+fn subscribe<S: Storage>(
+    req: &JrpcEnvelope,
+    connection: &mut Connection<S>,
+) -> JrpcResult<Response> {
+    let params: Request = parse_params(req.params.clone())?;
+    if connection.subscriptions.contains_key(&params.query) {
+        return Err(JrpcError::InternalError(String::from("already subscribed")));
+    }
+    if connection.subscriptions.len() >= MAX_SUBSCRIPTIONS_PER_CLIENT {
+        return Err(JrpcError::InternalError(format!(
+            "max_subscriptions_per_client {} reached",
+            MAX_SUBSCRIPTIONS_PER_CLIENT
+        )));
+    }
+    let query: Query = params
+        .query
+        .parse()
+        .map_err(|e| JrpcError::InternalError(format!("failed to parse query: {}", e)))?;
+    log!(Log::Websocket, "Subscribing to '{}'", query.as_str());
+    let events = connection.node.read().events().subscribe();
+    let subscription = tokio::spawn(forward_events(
+        query,
+        event_id(&req.response_id()),
+        events,
+        connection.sender.clone(),
+        connection.slow_client.clone(),
+    ));
+    connection.subscriptions.insert(params.query, subscription);
     Ok(Response {})
 }
+
+/// Handles the /unsubscribe endpoint, which stops the subscription to the given query.
+fn unsubscribe<S: Storage>(
+    params: UnsubscribeRequest,
+    connection: &mut Connection<S>,
+) -> JrpcResult<UnsubscribeResponse> {
+    let subscription = connection
+        .subscriptions
+        .remove(&params.query)
+        .ok_or_else(|| JrpcError::InternalError(String::from("subscription not found")))?;
+    log!(Log::Websocket, "Unsubscribing from '{}'", params.query);
+    subscription.abort();
+    Ok(UnsubscribeResponse {})
+}
+
+/// Handles the /unsubscribe_all endpoint, which stops all the subscriptions of the client.
+fn unsubscribe_all<S: Storage>(
+    _params: UnsubscribeAllRequest,
+    connection: &mut Connection<S>,
+) -> JrpcResult<UnsubscribeResponse> {
+    if connection.subscriptions.is_empty() {
+        return Err(JrpcError::InternalError(String::from(
+            "subscription not found",
+        )));
+    }
+    for (_, subscription) in connection.subscriptions.drain() {
+        subscription.abort();
+    }
+    Ok(UnsubscribeResponse {})
+}
+
+/// The id of the events of a subscription: the id of the subscribe request suffixed by `#event`.
+fn event_id(id: &Value) -> Value {
+    let id = match id {
+        Value::String(id) => id.clone(),
+        id => id.to_string(),
+    };
+    Value::String(format!("{}#event", id))
+}
+
+/// Push the events matching `query` to the client, until the connection is closed. If the queue
+/// of the client is full, `slow_client` is notified and the subscription stops.
+async fn forward_events(
+    query: Query,
+    id: Value,
+    mut events: broadcast::Receiver<Arc<Event>>,
+    sender: Sender<String>,
+    slow_client: Arc<Notify>,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(count)) => {
+                log!(Log::Websocket, "Subscription lagged, {} events lost", count);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if !query.matches(&event.attributes) {
+            continue;
+        }
+        let msg = serde_json::to_string(&JrpcResponse {
+            id: id.clone(),
+            jsonrpc: JRPC_VERSION.to_string(),
+            result: Some(EventMessage {
+                query: query.as_str(),
+                data: &event.data,
+                events: &event.attributes,
+            }),
+            error: None,
+        })
+        .unwrap();
+        match sender.try_send(msg) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                slow_client.notify_one();
+                break;
+            }
+            Err(TrySendError::Closed(_)) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::node::Node;
+    use crate::store::Memory;
+
+    fn request(method: &str, query: &str) -> String {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": "1",
+            "method": method,
+            "params": { "query": query },
+        })
+        .to_string()
+    }
+
+    fn connection(
+        node: &node::SharedNode<Memory>,
+        capacity: usize,
+    ) -> (Connection<Memory>, Receiver<String>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let connection = Connection {
+            node: node.clone(),
+            methods: Jrpc::methods(node.clone()),
+            sender,
+            slow_client: Arc::new(Notify::new()),
+            subscriptions: HashMap::new(),
+        };
+        (connection, receiver)
+    }
+
+    /// Send a request with the given query, returns the data of the error, if any.
+    async fn send(
+        connection: &mut Connection<Memory>,
+        method: &str,
+        query: &str,
+    ) -> Option<String> {
+        error_data(
+            &handle_request(&request(method, query), connection)
+                .await
+                .unwrap(),
+        )
+    }
+
+    fn error_data(response: &str) -> Option<String> {
+        let response: serde_json::Value = serde_json::from_str(response).unwrap();
+        response["error"]["data"].as_str().map(String::from)
+    }
+
+    #[tokio::test]
+    async fn subscriptions() {
+        let node = Node::new(&Config::default()).shared();
+        let (mut connection, mut receiver) = connection(&node, 10);
+
+        assert_eq!(
+            send(&mut connection, "subscribe", "tm.event='NewBlock'").await,
+            None
+        );
+        assert_eq!(
+            send(&mut connection, "subscribe", "tm.event='NewBlock'").await,
+            Some(String::from("already subscribed"))
+        );
+        assert!(send(&mut connection, "subscribe", "tm.event=")
+            .await
+            .is_some());
+
+        // Events matching the query are pushed with the id of the subscription
+        node.grow();
+        let event: serde_json::Value =
+            serde_json::from_str(&receiver.recv().await.unwrap()).unwrap();
+        assert_eq!(event["id"], "1#event");
+        assert_eq!(event["result"]["query"], "tm.event='NewBlock'");
+        assert_eq!(event["result"]["data"]["type"], "tendermint/event/NewBlock");
+
+        for height in 1..MAX_SUBSCRIPTIONS_PER_CLIENT {
+            assert_eq!(
+                send(
+                    &mut connection,
+                    "subscribe",
+                    &format!("tx.height={}", height)
+                )
+                .await,
+                None
+            );
+        }
+        assert_eq!(
+            send(&mut connection, "subscribe", "tm.event='Tx'").await,
+            Some(String::from("max_subscriptions_per_client 5 reached"))
+        );
+
+        assert_eq!(
+            send(&mut connection, "unsubscribe", "tm.event='NewBlock'").await,
+            None
+        );
+        assert_eq!(
+            send(&mut connection, "unsubscribe", "tm.event='NewBlock'").await,
+            Some(String::from("subscription not found"))
+        );
+        assert_eq!(
+            send(&mut connection, "subscribe", "tm.event='Tx'").await,
+            None
+        );
+        assert_eq!(send(&mut connection, "unsubscribe_all", "").await, None);
+        assert_eq!(
+            send(&mut connection, "unsubscribe_all", "").await,
+            Some(String::from("subscription not found"))
+        );
+    }
+
+    #[tokio::test]
+    async fn methods() {
+        let node = Node::new(&Config::default()).shared();
+        let (mut connection, _receiver) = connection(&node, 10);
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "status",
+        })
+        .to_string();
+
+        let response: serde_json::Value =
+            serde_json::from_str(&handle_request(&request, &mut connection).await.unwrap())
+                .unwrap();
+        assert_eq!(response["id"], 2);
+        assert!(response["result"]["sync_info"].is_object());
+
+        let response: serde_json::Value = serde_json::from_str(
+            &handle_request(&request.replace("status", "foo"), &mut connection)
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response["error"]["code"], -32601);
+
+        // Batches are handled as by the HTTP API
+        let batch = format!("[{},{}]", request, request.replace("status", "foo"));
+        let response: serde_json::Value =
+            serde_json::from_str(&handle_request(&batch, &mut connection).await.unwrap()).unwrap();
+        let responses = response.as_array().unwrap();
+        assert_eq!(responses.len(), 2);
+        assert!(responses[0]["result"]["sync_info"].is_object());
+        assert_eq!(responses[1]["error"]["code"], -32601);
+        let response: serde_json::Value =
+            serde_json::from_str(&handle_request("[]", &mut connection).await.unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], -32600);
+
+        // Notifications get no response
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "status",
+        })
+        .to_string();
+        assert_eq!(handle_request(&notification, &mut connection).await, None);
+        let batch = format!("[{},{}]", notification, request);
+        let response: serde_json::Value =
+            serde_json::from_str(&handle_request(&batch, &mut connection).await.unwrap()).unwrap();
+        assert_eq!(response.as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_client() {
+        let node = Node::new(&Config::default()).shared();
+        let (mut connection, _receiver) = connection(&node, 1);
+        let request = request("subscribe", "tm.event='NewBlock'");
+        assert_eq!(
+            error_data(&handle_request(&request, &mut connection).await.unwrap()),
+            None
+        );
+
+        // The first event fills the queue, which is never read
+        node.grow();
+        node.grow();
+        time::timeout(Duration::from_secs(1), connection.slow_client.notified())
+            .await
+            .expect("the slow client should be notified");
+    }
+
+    #[tokio::test]
+    async fn pings() {
+        let (ws, mut sent) = futures::channel::mpsc::unbounded::<Message>();
+        let (sender, receiver) = mpsc::channel(10);
+        let writer = tokio::spawn(write_messages(ws, receiver, Duration::from_millis(10)));
+
+        sender.send(String::from("hello")).await.unwrap();
+        assert_eq!(sent.next().await.unwrap(), Message::text("hello"));
+        assert!(sent.next().await.unwrap().is_ping());
+        assert!(sent.next().await.unwrap().is_ping());
+
+        // The writer stops once the queue is closed
+        drop(sender);
+        writer.await.unwrap();
+    }
+}
@@ -18,15 +18,24 @@
 mod logger;
 
 mod abci;
+mod accounts;
 mod avl;
 mod builder;
 mod chain;
+mod clock;
 mod config;
+mod events;
+mod gas;
+mod growth;
 mod grpc;
 mod init;
 mod jrpc;
 mod node;
 mod store;
 mod test_node;
+mod tx;
+mod upgrade;
 
 pub use builder::Tendermock;
+pub use clock::{Clock, ManualClock};
+pub use growth::GrowthHandle;
@@ -1,58 +1,105 @@
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use sha2::{Digest, Sha256};
+use tendermint::abci::transaction::Hash as TxHash;
+use tendermint::abci::Transaction;
 use tendermint::net::Address;
-use tendermint::{chain, node};
-use tendermint_rpc::endpoint::status::SyncInfo;
+use tendermint::{block, chain, node, validator};
+use tendermint_rpc::endpoint::broadcast::tx_commit::TxResult;
+use tendermint_testgen::{Generator, Validator};
 
-use crate::chain::Chain;
-use crate::config::Config;
+use crate::chain::{to_full_block, Chain};
+use crate::config::{self, Config, GasConfig, WebsocketConfig};
+use crate::events::{Event, EventBus, TxInfo};
+use crate::gas::MeteredStore;
+use crate::growth::GrowthControl;
+use crate::logger::Log;
+use crate::node::objects::{IndexedTx, SyncInfo};
 use crate::node::shared::SharedNode;
 use crate::store::Memory;
 use crate::store::Storage;
+use crate::tx::TxContext;
+use crate::upgrade::{self, UpgradePlan};
 
 /// A bare node contains:
 ///     - a chain, plus its associated store,
 ///     - and some meta-data.
 pub struct Node<S: Storage> {
     chain: Chain<S>,
-    chain_id: tendermint::chain::Id,
     info: node::Info,
     consensus_params: tendermint::consensus::Params,
+    /// Index of the delivered transactions, by hash.
+    txs: HashMap<TxHash, IndexedTx>,
+    gas_config: GasConfig,
+    websocket_config: WebsocketConfig,
+    account_prefix: String,
+    /// Controls the production of new blocks.
+    growth: Arc<GrowthControl>,
+    /// The validator run by the node.
+    validator: Validator,
+    /// Publishes the events of the node.
+    events: EventBus,
 }
 
+/// The address reported by nodes that are not served on any interface.
+const DEFAULT_RPC_ADDRESS: &str = "127.0.0.1:26657";
+
 impl Node<Memory> {
     pub fn new(config: &Config) -> Self {
-        // TODO: allow to pass customized values
-        let info = node::Info {
-            // Node id
-            id: node::Id::new([61; 20]),
-            listen_addr: node::info::ListenAddress::new(String::from("localhost:26657")),
-            network: chain::Id::from_str(&config.chain_id).unwrap(),
-            protocol_version: node::info::ProtocolVersionInfo {
-                p2p: 0,
-                block: 0,
-                app: 0,
-            },
-            version: serde_json::from_value(serde_json::Value::String("v0.1.0".to_string()))
-                .unwrap(),
-            channels: serde_json::from_value(serde_json::Value::String("channels".to_string()))
-                .unwrap(),
-            moniker: tendermint::Moniker::from_str("moniker").unwrap(),
-            other: node::info::OtherInfo {
-                tx_index: node::info::TxIndexStatus::Off,
-                rpc_address: Address::from_str("tcp://127.0.0.1:26657").unwrap(),
-            },
-        };
+        Node::new_at(config, DEFAULT_RPC_ADDRESS.parse().unwrap())
+    }
+
+    /// Create a node reporting `rpc_address` as its address.
+    pub fn new_at(config: &Config, rpc_address: SocketAddr) -> Self {
+        let info = new_node_info(&config.node_info, &config.chain_id, rpc_address);
+
+        let validators = to_testgen_validators(&config.validators);
+        let validator = validators[0].clone();
+        let mut validator_updates = BTreeMap::new();
+        for update in &config.validator_updates {
+            validator_updates.insert(update.height, to_testgen_validators(&update.validators));
+        }
+        let upgrade = config.upgrade.as_ref().map(|upgrade| UpgradePlan {
+            height: upgrade.height,
+            chain_id: upgrade
+                .chain_id
+                .clone()
+                .unwrap_or_else(|| upgrade::next_chain_id(&config.chain_id)),
+        });
 
         Node {
-            chain: Chain::new(Memory::new()),
-            chain_id: tendermint::chain::Id::try_from(config.chain_id.to_owned()).unwrap(),
+            chain: Chain::new(
+                Memory::new(),
+                &config.chain_id,
+                validators,
+                validator_updates,
+                config.serve_pending_block,
+                config.clock.clone(),
+                upgrade,
+            ),
             consensus_params: config.consensus_params.clone(),
             info,
+            txs: HashMap::new(),
+            gas_config: config.gas.clone(),
+            websocket_config: config.websocket.clone(),
+            account_prefix: config.account_prefix.clone(),
+            growth: Arc::new(GrowthControl::new()),
+            validator,
+            events: EventBus::new(),
         }
     }
 
+    /// Use a growth control shared with other components, such as growth handles.
+    pub fn with_growth_control(mut self, growth: Arc<GrowthControl>) -> Self {
+        growth.record_height(self.chain.get_absolute_height());
+        self.growth = growth;
+        self
+    }
+
     /// Return the node in an Arc<RwLock> wrapper, ready to be shared among threads.
     pub fn shared(self) -> SharedNode<Memory> {
         SharedNode::new(self)
@@ -60,44 +107,256 @@ impl Node<Memory> {
 }
 
 impl<S: Storage> Node<S> {
-    pub fn store(&self) -> &S {
-        &self.chain.get_store()
+    /// Returns the store, accesses are not charged any gas.
+    pub fn store(&self) -> MeteredStore<S> {
+        MeteredStore::new(self.chain.get_store(), None, None, &self.gas_config)
+    }
+
+    /// Returns the store as seen by a transaction: accesses are charged to its gas meter and
+    /// writes are buffered in its branch.
+    pub fn tx_store<'a>(&'a self, tx: &'a TxContext) -> MeteredStore<'a, S> {
+        MeteredStore::new(
+            self.chain.get_store(),
+            Some(tx.meter()),
+            Some(tx.branch()),
+            &self.gas_config,
+        )
     }
 
     pub fn chain(&self) -> &Chain<S> {
         &self.chain
     }
 
-    pub fn info(&self) -> &node::Info {
-        &self.info
+    /// Returns the node info, its network is the chain id of the current revision.
+    pub fn info(&self) -> node::Info {
+        node::Info {
+            network: self.chain_id(),
+            ..self.info.clone()
+        }
     }
 
-    pub fn chain_id(&self) -> &chain::Id {
-        &self.chain_id
+    /// Returns the chain id of the current revision.
+    pub fn chain_id(&self) -> chain::Id {
+        chain::Id::try_from(self.chain.get_chain_id()).unwrap()
     }
 
     pub fn consensus_params(&self) -> &tendermint::consensus::Params {
         &self.consensus_params
     }
 
-    pub fn grow(&self) {
+    pub fn gas_config(&self) -> &GasConfig {
+        &self.gas_config
+    }
+
+    pub fn websocket_config(&self) -> &WebsocketConfig {
+        &self.websocket_config
+    }
+
+    pub fn account_prefix(&self) -> &str {
+        &self.account_prefix
+    }
+
+    pub fn growth(&self) -> &Arc<GrowthControl> {
+        &self.growth
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Grow the chain with an empty block, the heights recorded by the growth control count the
+    /// blocks of all revisions. No block is produced while the chain is halted or stalled, in
+    /// which case false is returned.
+    pub fn grow(&self) -> bool {
+        if !self.grow_chain() {
+            return false;
+        }
+        self.publish_block(&[]);
+        true
+    }
+
+    /// Grow the chain with a block containing `txs`, given with their hashes and delivery
+    /// results, see `grow`. The transactions are indexed before the events of the block are
+    /// published.
+    pub fn grow_with_txs(&mut self, txs: Vec<(TxHash, Transaction, TxResult)>) -> bool {
+        if !self.grow_chain() {
+            return false;
+        }
+        let height = self.latest_height();
+        let txs: Vec<(TxHash, IndexedTx)> = txs
+            .into_iter()
+            .enumerate()
+            .map(|(index, (hash, tx, result))| {
+                let index = index as u32;
+                let tx = IndexedTx {
+                    height,
+                    index,
+                    tx,
+                    result,
+                };
+                (hash, tx)
+            })
+            .collect();
+        for (hash, tx) in &txs {
+            self.txs.insert(*hash, tx.clone());
+        }
+        self.publish_block(&txs);
+        true
+    }
+
+    fn grow_chain(&self) -> bool {
+        if let Err(e) = self.growth.check_growth() {
+            log!(Log::Chain, "No block produced: {}", e);
+            return false;
+        }
+        if let Some(plan) = self.chain.get_upgrade_plan() {
+            // The upgraded states are committed in the block preceding the upgrade height
+            if self.chain.get_height().revision_height + 2 == plan.height {
+                upgrade::store_upgraded_states(self, &plan);
+            }
+        }
         self.chain.grow();
+        self.growth.record_height(self.chain.get_absolute_height());
+        true
+    }
+
+    /// Publish the events of the latest block, which contains `txs`, as Tendermint does: the
+    /// `Tx` events follow the ones of the block.
+    fn publish_block(&self, txs: &[(TxHash, IndexedTx)]) {
+        let block = to_full_block(
+            self.chain
+                .get_block(0)
+                .expect("The chain should always contain a block"),
+            txs.iter().map(|(_, tx)| tx.tx.clone()).collect(),
+        );
+        let header = block.header.clone();
+        self.events.publish(Event::new_block(block));
+        self.events
+            .publish(Event::new_block_header(header, txs.len()));
+        for (hash, tx) in txs {
+            self.events.publish(Event::tx(
+                *hash,
+                TxInfo {
+                    height: tx.height,
+                    index: tx.index,
+                    tx: tx.tx.clone(),
+                    result: tx.result.clone(),
+                },
+            ));
+        }
+    }
+
+    fn latest_height(&self) -> block::Height {
+        self.chain
+            .get_block(0)
+            .expect("The chain should always contain a block")
+            .signed_header
+            .header
+            .height
+    }
+
+    /// Returns the indexed transactions of the block at `height`, in their order within the
+    /// block.
+    pub fn get_block_txs(&self, height: block::Height) -> Vec<Transaction> {
+        let mut txs: Vec<&IndexedTx> = self.txs.values().filter(|tx| tx.height == height).collect();
+        txs.sort_by_key(|tx| tx.index);
+        txs.into_iter().map(|tx| tx.tx.clone()).collect()
+    }
+
+    /// Returns the indexed transaction with the given hash, if any.
+    pub fn get_tx(&self, hash: &TxHash) -> Option<&IndexedTx> {
+        self.txs.get(hash)
     }
 
-    /// Get sync infos. For now only the field `latest_block_height` contains a valid value.
+    /// Get sync infos, from the earliest and latest blocks of the current revision. The node is
+    /// catching up while the chain is stalled.
     pub fn get_sync_info(&self) -> SyncInfo {
-        let latest_block_height = self.chain.get_height();
+        let latest = self
+            .chain
+            .get_block(0)
+            .expect("The chain should always contain a block")
+            .signed_header
+            .header;
+        let earliest = self
+            .chain
+            .get_block(1)
+            .expect("The chain should always contain a block")
+            .signed_header
+            .header;
+        SyncInfo {
+            latest_block_hash: latest.hash(),
+            latest_app_hash: latest.app_hash,
+            latest_block_height: latest.height,
+            latest_block_time: latest.time,
+            earliest_block_hash: earliest.hash(),
+            earliest_app_hash: earliest.app_hash,
+            earliest_block_height: earliest.height,
+            earliest_block_time: earliest.time,
+            catching_up: self.growth.is_catching_up(),
+        }
+    }
+
+    /// Returns the validator info of the node, which runs the first validator of the
+    /// configuration. Its voting power is zero if it is not part of the latest validator set.
+    pub fn get_validator_info(&self) -> validator::Info {
+        let info = self
+            .validator
+            .generate()
+            .expect("[Internal] Validators should be valid.");
         let block = self
             .chain
             .get_block(0)
             .expect("The chain should always contain a block");
-        let hash = block.signed_header.header.hash();
-        SyncInfo {
-            latest_block_hash: hash,
-            latest_app_hash: tendermint::AppHash::try_from(vec![61_u8; 32]).unwrap(),
-            latest_block_height: (latest_block_height.revision_height as u32).into(),
-            latest_block_time: block.signed_header.header.time,
-            catching_up: false,
+        block
+            .validators
+            .validators()
+            .iter()
+            .find(|validator| validator.address == info.address)
+            .cloned()
+            .unwrap_or(validator::Info {
+                voting_power: 0_u32.into(),
+                proposer_priority: 0.into(),
+                ..info
+            })
+    }
+}
+
+/// Build the node info from the configuration, the node is reachable at `rpc_address`.
+fn new_node_info(config: &config::NodeInfo, chain_id: &str, rpc_address: SocketAddr) -> node::Info {
+    let id = match &config.id {
+        Some(id) => node::Id::from_str(id).unwrap_or_else(|_| panic!("Invalid node id: {}", id)),
+        None => {
+            // Nodes with distinct monikers or addresses have distinct ids
+            let digest = Sha256::digest(format!("{}@{}", config.moniker, rpc_address).as_bytes());
+            let mut id = [0; 20];
+            id.copy_from_slice(&digest[..20]);
+            node::Id::new(id)
         }
+    };
+    node::Info {
+        id,
+        listen_addr: node::info::ListenAddress::new(rpc_address.to_string()),
+        network: chain::Id::from_str(chain_id).unwrap(),
+        protocol_version: node::info::ProtocolVersionInfo {
+            p2p: config.protocol_version.p2p,
+            block: config.protocol_version.block,
+            app: config.protocol_version.app,
+        },
+        version: serde_json::from_value(serde_json::Value::String(config.version.clone()))
+            .unwrap_or_else(|_| panic!("Invalid node version: {}", config.version)),
+        channels: serde_json::from_value(serde_json::Value::String("channels".to_string()))
+            .unwrap(),
+        moniker: tendermint::Moniker::from_str(&config.moniker).unwrap(),
+        other: node::info::OtherInfo {
+            tx_index: node::info::TxIndexStatus::Off,
+            rpc_address: Address::from_str(&format!("tcp://{}", rpc_address)).unwrap(),
+        },
     }
 }
+
+fn to_testgen_validators(validators: &[config::Validator]) -> Vec<Validator> {
+    validators
+        .iter()
+        .map(|v| Validator::new(&v.id).voting_power(v.voting_power))
+        .collect()
+}
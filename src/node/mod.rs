@@ -14,5 +14,5 @@ mod shared;
 
 pub use bare::Node;
 pub use error::Error;
-pub use objects::Counter;
+pub use objects::{Counter, IndexedTx, SyncInfo};
 pub use shared::SharedNode;
@@ -1,6 +1,9 @@
 use std::convert::{TryFrom, TryInto};
 
 use serde::{Deserialize, Serialize};
+use tendermint::abci::Transaction;
+use tendermint::{block, AppHash, Hash, Time};
+use tendermint_rpc::endpoint::broadcast::tx_commit::TxResult;
 
 use crate::node::Error;
 
@@ -18,6 +21,36 @@ impl Connections {
     }
 }
 
+/// A transaction included in a block, as kept by the node's transaction index.
+#[derive(Clone, Debug)]
+pub struct IndexedTx {
+    /// Height of the block containing the transaction.
+    pub height: block::Height,
+    /// Index of the transaction within its block.
+    pub index: u32,
+    /// The raw transaction.
+    pub tx: Transaction,
+    /// The result of the transaction delivery.
+    pub result: TxResult,
+}
+
+/// The synchronization state of a node, as reported by the /status endpoint.
+///
+/// Unlike the `SyncInfo` of `tendermint_rpc`, it includes the earliest block fields of recent
+/// Tendermint versions.
+#[derive(Serialize, Clone, Debug)]
+pub struct SyncInfo {
+    pub latest_block_hash: Hash,
+    pub latest_app_hash: AppHash,
+    pub latest_block_height: block::Height,
+    pub latest_block_time: Time,
+    pub earliest_block_hash: Hash,
+    pub earliest_app_hash: AppHash,
+    pub earliest_block_height: block::Height,
+    pub earliest_block_time: Time,
+    pub catching_up: bool,
+}
+
 /// A counter type for representation of client, connection, or channel counters.
 /// The primary use-case for this type is for interfacing with the storage: a counter can be read
 /// or written easily due to its support for serialization to/from `[u8]`.
@@ -30,24 +30,36 @@ use prost::Message;
 use prost_types::Any;
 use tendermint_proto::Protobuf;
 
+use crate::gas::MeteredStore;
 use crate::grpc::GrpcContext;
 use crate::logger::Log;
 use crate::node::bare::Node;
 use crate::node::objects::{Connections, Counter};
 use crate::store::{Location, PathValue, Storage};
+use crate::tx::TxContext;
 
 // System constant
 const COMMITMENT_PREFIX: &str = "store/ibc/key";
 
 /// An `Arc<RwLock<>>` wrapper around a Node.
+///
+/// Transactions are executed one at a time (see `lock_txs`), through a `SharedNode` bound to the
+/// transaction (see `with_tx`), so that the IBC modules access the store through the gas meter and
+/// the branch of the transaction.
 pub struct SharedNode<S: Storage> {
     node: std::sync::Arc<std::sync::RwLock<Node<S>>>,
+    /// Serializes the execution of transactions and the production of blocks.
+    txs: std::sync::Arc<std::sync::Mutex<()>>,
+    /// The transaction being executed through this node, if any.
+    tx: Option<std::sync::Arc<TxContext>>,
 }
 
 impl<S: Storage> Clone for SharedNode<S> {
     fn clone(&self) -> Self {
         Self {
             node: std::sync::Arc::clone(&self.node),
+            txs: std::sync::Arc::clone(&self.txs),
+            tx: self.tx.clone(),
         }
     }
 }
@@ -56,6 +68,31 @@ impl<S: Storage> SharedNode<S> {
     pub fn new(bare: Node<S>) -> Self {
         Self {
             node: std::sync::Arc::new(std::sync::RwLock::new(bare)),
+            txs: std::sync::Arc::new(std::sync::Mutex::new(())),
+            tx: None,
+        }
+    }
+
+    /// Returns a handle to the node bound to a transaction, its store accesses are charged to the
+    /// gas meter of the transaction and its writes are buffered in the branch of the transaction.
+    pub fn with_tx(&self, tx: std::sync::Arc<TxContext>) -> Self {
+        Self {
+            tx: Some(tx),
+            ..self.clone()
+        }
+    }
+
+    /// Acquire the transaction lock: transactions are executed one at a time, and no block is
+    /// produced while the lock is held.
+    pub fn lock_txs(&self) -> std::sync::MutexGuard<()> {
+        self.txs.lock().unwrap()
+    }
+
+    /// Returns the store of the node, as seen by the transaction bound to this handle (if any).
+    fn store<'a>(&'a self, node: &'a Node<S>) -> MeteredStore<'a, S> {
+        match &self.tx {
+            Some(tx) => node.tx_store(tx),
+            None => node.store(),
         }
     }
 
@@ -69,9 +106,11 @@ impl<S: Storage> SharedNode<S> {
         self.node.write().unwrap()
     }
 
-    /// Grow the chain.
-    pub fn grow(&self) {
-        self.node.write().unwrap().grow();
+    /// Grow the chain, once the transactions being executed (if any) are done. Returns false if
+    /// the chain is halted or stalled.
+    pub fn grow(&self) -> bool {
+        let _txs = self.lock_txs();
+        self.node.write().unwrap().grow()
     }
 }
 
@@ -79,7 +118,7 @@ impl<S: Storage> ClientReader for SharedNode<S> {
     fn client_type(&self, client_id: &ClientId) -> Option<ClientType> {
         let path = format!("clients/{}/clientType", client_id.as_str());
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         let client_type = store.get(Location::LatestStable, path.as_bytes())?;
         let client_type = String::from_utf8(client_type.to_vec());
         match client_type {
@@ -91,7 +130,7 @@ impl<S: Storage> ClientReader for SharedNode<S> {
     fn client_state(&self, client_id: &ClientId) -> Option<AnyClientState> {
         let path = format!("clients/{}/clientState", client_id.as_str());
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         let value = store.get(Location::LatestStable, path.as_bytes())?;
         let client_state = AnyClientState::decode(value.as_slice());
         client_state.ok()
@@ -104,7 +143,7 @@ impl<S: Storage> ClientReader for SharedNode<S> {
             height.to_string()
         );
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         let value = store.get(Location::LatestStable, path.as_bytes())?;
         let consensus_state = AnyConsensusState::decode(value.as_slice());
         consensus_state.ok()
@@ -113,7 +152,7 @@ impl<S: Storage> ClientReader for SharedNode<S> {
     fn client_counter(&self) -> u64 {
         let path = "meta/clients/counter".to_string();
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
 
         match store.get(Location::LatestStable, path.as_bytes()) {
             None => 0,
@@ -133,7 +172,7 @@ impl<S: Storage> ClientKeeper for SharedNode<S> {
     ) -> Result<(), ClientError> {
         let path = format!("clients/{}/clientType", client_id.as_str());
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         store.set(
             path.clone().into_bytes(),
             client_type.as_string().as_bytes().to_owned(),
@@ -154,7 +193,7 @@ impl<S: Storage> ClientKeeper for SharedNode<S> {
         data.encode(&mut buffer)
             .map_err(|e| ClientErrorKind::InvalidRawClientState.context(e))?;
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         store.set(path.clone().into_bytes(), buffer);
         log!(Log::Store, "Storing client state at {}", path);
         Ok(())
@@ -176,7 +215,7 @@ impl<S: Storage> ClientKeeper for SharedNode<S> {
         data.encode(&mut buffer)
             .map_err(|e| ClientErrorKind::InvalidRawConsensusState.context(e))?;
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         store.set(path.clone().into_bytes(), buffer);
         log!(Log::Store, "Storing client consensus state at {}", path);
         Ok(())
@@ -186,7 +225,7 @@ impl<S: Storage> ClientKeeper for SharedNode<S> {
         let cnt = Counter::from(self.client_counter() + 1);
         let path = "meta/clients/counter".to_string();
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         log!(
             Log::Store,
             "Storing new client counter state at {}: {}",
@@ -208,7 +247,7 @@ impl<S: Storage> ConnectionKeeper for SharedNode<S> {
         let raw: RawConnectionEnd = connection_end.to_owned().into();
         raw.encode(&mut buffer).unwrap();
         let node = self.write();
-        node.store().set(path.into_bytes(), buffer);
+        self.store(&node).set(path.into_bytes(), buffer);
         Ok(())
     }
 
@@ -219,7 +258,7 @@ impl<S: Storage> ConnectionKeeper for SharedNode<S> {
     ) -> Result<(), ConnectionError> {
         let path = format!("clients/{}/connections", client_id.as_str());
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         let connections = store
             .get(Location::LatestStable, path.as_bytes())
             .unwrap_or_default();
@@ -237,7 +276,7 @@ impl<S: Storage> ConnectionKeeper for SharedNode<S> {
         let cnt = Counter::from(self.connection_counter() + 1);
         let path = "meta/connections/counter".to_string();
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         store.set(path.into_bytes(), cnt.into());
     }
 }
@@ -246,7 +285,7 @@ impl<S: Storage> ConnectionReader for SharedNode<S> {
     fn connection_end(&self, connection_id: &ConnectionId) -> Option<ConnectionEnd> {
         let path = format!("connections/{}", connection_id.as_str());
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         let value = store.get(Location::LatestStable, path.as_bytes())?;
         let raw = RawConnectionEnd::decode(&*value).ok()?;
         ConnectionEnd::try_from(raw).ok()
@@ -300,7 +339,7 @@ impl<S: Storage> ConnectionReader for SharedNode<S> {
     fn connection_counter(&self) -> u64 {
         let path = "meta/connections/counter".to_string();
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
 
         match store.get(Location::LatestStable, path.as_bytes()) {
             None => 0,
@@ -487,7 +526,7 @@ impl<S: Storage> GrpcContext for SharedNode<S> {
         log!(Log::Store, "Fetching all consensus state of {}", client_id);
         let path = format!("clients/{}/consensusState/", client_id.as_str(),);
         let node = self.read();
-        let store = node.store();
+        let store = self.store(&node);
         let hits = store.get_by_prefix(Location::LatestStable, path.as_bytes());
 
         // Convert each pair into a `ConsensusStateWithHeight`
@@ -15,6 +15,12 @@
 //!     1. a pending location, which represents the current block being processed, but not yet
 //!         committed;
 //!     2. a stable location, which is versioned by height.
+//!
+//! Writes to the pending location can also be grouped into a `Branch`, owned by a transaction,
+//! which can later be either committed to the store or discarded (e.g. when the transaction
+//! fails).
+use std::collections::BTreeMap;
+use std::sync::Mutex;
 
 pub use memory::Memory;
 
@@ -55,6 +61,56 @@ pub trait Storage: std::fmt::Debug {
     /// Returns `None` if nothing found.
     fn get_by_prefix(&self, loc: Location, prefix: &[u8]) -> Vec<PathValue>;
 
+    /// Returns the root hash of the state at a given location, which is used as the app hash.
+    /// Returns `None` if the state is empty or the location does not exist.
+    fn root_hash(&self, loc: Location) -> Option<Vec<u8>>;
+
     /// Freeze the pending store by adding it to the committed chain, and create a new pending.
     fn grow(&self);
 }
+
+/// A branch of the pending location: writes are buffered in the branch, and reads of the pending
+/// location see the writes of the branch on top of the store.
+///
+/// Each transaction owns its branch, so that the writes of concurrent transactions (or
+/// simulations) never mix.
+#[derive(Debug, Default)]
+pub struct Branch {
+    writes: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl Branch {
+    pub fn new() -> Self {
+        Branch::default()
+    }
+
+    /// Buffer a write to the pending location.
+    pub fn set(&self, path: Vec<u8>, value: Vec<u8>) {
+        self.writes.lock().unwrap().insert(path, value);
+    }
+
+    /// Access the value at a given path and location, the pending location includes the writes
+    /// of the branch.
+    pub fn get<S: Storage>(&self, store: &S, loc: Location, path: &[u8]) -> Option<Vec<u8>> {
+        if let Location::Pending = loc {
+            if let Some(value) = self.writes.lock().unwrap().get(path) {
+                return Some(value.clone());
+            }
+        }
+        store.get(loc, path)
+    }
+
+    /// Apply the writes of the branch to the pending location of the store, the branch is then
+    /// empty.
+    pub fn commit<S: Storage>(&self, store: &S) {
+        let writes = std::mem::take(&mut *self.writes.lock().unwrap());
+        for (path, value) in writes {
+            store.set(path, value);
+        }
+    }
+
+    /// Drop the writes of the branch.
+    pub fn discard(&self) {
+        self.writes.lock().unwrap().clear();
+    }
+}
@@ -112,6 +112,20 @@ impl Storage for Memory {
         }
     }
 
+    fn root_hash(&self, loc: Location) -> Option<Vec<u8>> {
+        let store = self.store.read().unwrap();
+        let hash = match loc {
+            Location::Pending => {
+                drop(store); // Release lock on the stable store
+                let pending = self.pending.read().unwrap();
+                return pending.root_hash().map(|hash| hash.as_bytes().to_vec());
+            }
+            Location::LatestStable => store.last().unwrap().root_hash(),
+            Location::Stable(height) => store.get(height as usize)?.root_hash(),
+        };
+        hash.map(|hash| hash.as_bytes().to_vec())
+    }
+
     fn grow(&self) {
         let mut store = self.store.write().unwrap();
         let pending = self.pending.write().unwrap();
@@ -123,7 +137,7 @@ impl Storage for Memory {
 #[cfg(test)]
 mod tests {
     use crate::store::Location;
-    use crate::store::{Memory, Storage};
+    use crate::store::{Branch, Memory, Storage};
 
     #[test]
     fn store() {
@@ -131,6 +145,51 @@ mod tests {
         test_with_store(store)
     }
 
+    #[test]
+    fn branch() {
+        let store = Memory::new();
+        let path = b"foo/bar";
+        store.set(path.to_vec(), b"hello".to_vec());
+
+        // Writes are only visible through the branch until it is committed
+        let branch = Branch::new();
+        branch.set(path.to_vec(), b"hello2".to_vec());
+        assert_eq!(
+            branch.get(&store, Location::Pending, path),
+            Some(b"hello2".to_vec())
+        );
+        assert_eq!(store.get(Location::Pending, path), Some(b"hello".to_vec()));
+        branch.discard();
+        assert_eq!(
+            branch.get(&store, Location::Pending, path),
+            Some(b"hello".to_vec())
+        );
+
+        branch.set(path.to_vec(), b"hello3".to_vec());
+        branch.commit(&store);
+        assert_eq!(store.get(Location::Pending, path), Some(b"hello3".to_vec()));
+        assert_eq!(branch.get(&store, Location::LatestStable, path), None);
+    }
+
+    #[test]
+    fn root_hash() {
+        let store = Memory::new();
+        assert_eq!(store.root_hash(Location::LatestStable), None);
+
+        store.set(b"foo/bar".to_vec(), b"hello".to_vec());
+        let pending_hash = store.root_hash(Location::Pending);
+        assert!(pending_hash.is_some());
+        store.grow();
+        assert_eq!(store.root_hash(Location::LatestStable), pending_hash);
+        assert_eq!(store.root_hash(Location::Stable(1)), pending_hash);
+        assert_eq!(store.root_hash(Location::Stable(0)), None);
+
+        store.set(b"foo/bar".to_vec(), b"hello2".to_vec());
+        store.grow();
+        assert_ne!(store.root_hash(Location::LatestStable), pending_hash);
+        assert_eq!(store.root_hash(Location::Stable(1)), pending_hash);
+    }
+
     fn test_with_store<T: Storage>(store: T) {
         let data1 = b"hello";
         let data2 = b"hello2";
@@ -17,8 +17,10 @@ mod tests {
     use ibc::Height;
     use tendermint::trust_threshold::TrustThresholdFraction;
 
+    use crate::abci;
     use crate::config;
     use crate::node::*;
+    use crate::store::Storage;
 
     #[test]
     /// Test storage and retrieval of client and consensus states.
@@ -47,6 +49,106 @@ mod tests {
         assert_eq!(consensus_state, retrieved_consensus);
     }
 
+    #[test]
+    /// Test that the configured validators sign the blocks.
+    fn validators() {
+        let mut config = config::Config::default();
+        config.validators = vec![
+            config::Validator {
+                id: String::from("alice"),
+                voting_power: 10,
+            },
+            config::Validator {
+                id: String::from("bob"),
+                voting_power: 30,
+            },
+            config::Validator {
+                id: String::from("carol"),
+                voting_power: 60,
+            },
+        ];
+        let node = Node::new(&config);
+        node.grow();
+        let block = node.chain().get_block(0).unwrap();
+        let validators = block.validators.validators();
+        assert_eq!(validators.len(), 3);
+        let total_power: u64 = validators.iter().map(|v| v.voting_power.value()).sum();
+        assert_eq!(total_power, 100);
+        assert_eq!(
+            block.signed_header.commit.signatures.len(),
+            validators.len()
+        );
+    }
+
+    #[test]
+    /// Test that the status reports the node's validator and the earliest and latest blocks.
+    fn status() {
+        let node = Node::new(&config::Config::default());
+        node.grow();
+        node.grow();
+
+        let validator = node.get_validator_info();
+        let block = node.chain().get_block(0).unwrap();
+        assert!(block
+            .validators
+            .validators()
+            .iter()
+            .any(|v| v.address == validator.address));
+        assert_eq!(validator.voting_power.value(), 50);
+
+        let sync_info = node.get_sync_info();
+        assert_eq!(sync_info.earliest_block_height.value(), 1);
+        assert_eq!(sync_info.latest_block_height.value(), 3);
+        assert_eq!(
+            sync_info.latest_block_hash,
+            block.signed_header.header.hash()
+        );
+        assert!(sync_info.earliest_block_time < sync_info.latest_block_time);
+
+        // The app hashes are the ones of the block headers
+        assert_eq!(
+            sync_info.latest_app_hash,
+            block.signed_header.header.app_hash
+        );
+        let earliest = node.chain().get_block(1).unwrap();
+        assert_eq!(
+            sync_info.earliest_app_hash,
+            earliest.signed_header.header.app_hash
+        );
+
+        // The app hash of a block is the root hash of the store once the previous one is committed
+        let app_hash = sync_info.latest_app_hash;
+        node.store().set(b"foo".to_vec(), b"bar".to_vec());
+        node.grow();
+        assert_eq!(node.get_sync_info().latest_app_hash, app_hash);
+        node.grow();
+        let sync_info = node.get_sync_info();
+        assert!(!sync_info.latest_app_hash.value().is_empty());
+        assert_ne!(sync_info.latest_app_hash, app_hash);
+        assert_eq!(
+            abci::get_info(&node).last_block_app_hash,
+            sync_info.latest_app_hash.value()
+        );
+    }
+
+    #[test]
+    /// Test that nodes served at different addresses report distinct identities.
+    fn node_info() {
+        let mut config = config::Config::default();
+        config.node_info.moniker = String::from("scrooge");
+        let node_a = Node::new_at(&config, "127.0.0.1:5000".parse().unwrap());
+        let node_b = Node::new_at(&config, "127.0.0.1:5001".parse().unwrap());
+        let (info_a, info_b) = (node_a.info(), node_b.info());
+        assert_ne!(info_a.id, info_b.id);
+        assert_eq!(info_a.listen_addr.to_string(), "127.0.0.1:5000");
+        assert_eq!(info_a.other.rpc_address.to_string(), "tcp://127.0.0.1:5000");
+        assert_eq!(info_a.moniker.to_string(), "scrooge");
+
+        config.node_info.id = Some(String::from("3D3D3D3D3D3D3D3D3D3D3D3D3D3D3D3D3D3D3D3D"));
+        let node = Node::new(&config);
+        assert_eq!(node.info().id, tendermint::node::Id::new([61; 20]));
+    }
+
     fn dummy_consensus_state() -> AnyConsensusState {
         let root = CommitmentRoot::from_bytes(b"root");
         let tm_consensus_state = ConsensusState {
@@ -0,0 +1,547 @@
+//! # Transactions
+//!
+//! This module holds the transaction pipeline shared by the RPC interfaces. A raw transaction is
+//! decoded, its fee is checked against the gas configuration and its messages are delivered to
+//! the IBC modules. Each transaction has its own gas meter and its own branch of the store (see
+//! `TxContext`), and transactions are executed one at a time.
+//!
+//! As in the Cosmos SDK, the state changes of a transaction are discarded if its delivery fails
+//! (including when it runs out of gas), and failures are reported through the SDK error codes.
+//! Transactions can also be simulated, in which case none of their state changes are kept.
+use std::sync::{Arc, Mutex};
+
+use ibc::events::IbcEvent;
+use ibc::ics26_routing::handler::deliver;
+use ibc_proto::cosmos::tx::v1beta1::{AuthInfo, TxBody, TxRaw};
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tendermint::abci::responses::Codespace;
+use tendermint::abci::tag::Tag;
+use tendermint::abci::{transaction::Hash, Code, Event, Info, Log as AbciLog, Transaction};
+use tendermint::block;
+use tendermint_rpc::endpoint::broadcast::tx_commit::TxResult;
+
+use crate::accounts;
+use crate::config::GasConfig;
+use crate::gas::GasMeter;
+use crate::logger::Log;
+use crate::node::SharedNode;
+use crate::store::{Branch, Storage};
+
+pub const HASH_LENGTH: usize = 32; // tendermint::abci::transaction::hash::LENGTH is not exposed...
+
+// Cosmos SDK error codes, in the `sdk` codespace.
+const SDK_CODESPACE: &str = "sdk";
+const CODE_INTERNAL: u32 = 1;
+const CODE_TX_DECODE: u32 = 2;
+const CODE_OUT_OF_GAS: u32 = 11;
+const CODE_INSUFFICIENT_FEE: u32 = 13;
+
+/// The execution modes of a transaction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Mode {
+    /// Only run the checks that do not execute the messages (`CheckTx`).
+    Check,
+    /// Execute the messages and keep the state changes on success (`DeliverTx`).
+    Deliver,
+    /// Execute the messages without verifying the signatures nor keeping any state change.
+    Simulate,
+}
+
+/// The outcome of `broadcast_tx_commit`.
+pub struct Committed {
+    pub hash: Hash,
+    pub check_tx: TxResult,
+    pub deliver_tx: TxResult,
+    pub height: block::Height,
+}
+
+/// The state of a transaction being executed: its gas meter and its branch of the store, in
+/// which its writes are buffered until they are committed.
+#[derive(Debug)]
+pub struct TxContext {
+    meter: Mutex<GasMeter>,
+    branch: Branch,
+}
+
+impl TxContext {
+    pub fn new(gas_limit: u64) -> Self {
+        TxContext {
+            meter: Mutex::new(GasMeter::new(gas_limit)),
+            branch: Branch::new(),
+        }
+    }
+
+    pub fn meter(&self) -> &Mutex<GasMeter> {
+        &self.meter
+    }
+
+    pub fn branch(&self) -> &Branch {
+        &self.branch
+    }
+
+    /// Consume `amount` units of gas, `descriptor` describes the operation consuming the gas.
+    pub fn consume_gas(&self, amount: u64, descriptor: &'static str) {
+        self.meter.lock().unwrap().consume(amount, descriptor);
+    }
+
+    /// Returns a snapshot of the gas meter.
+    fn gas_meter(&self) -> GasMeter {
+        self.meter.lock().unwrap().clone()
+    }
+}
+
+/// A decoded transaction.
+struct DecodedTx {
+    body: TxBody,
+    auth_info: AuthInfo,
+    raw: TxRaw,
+}
+
+impl DecodedTx {
+    fn gas_limit(&self) -> u64 {
+        self.auth_info
+            .fee
+            .as_ref()
+            .map(|fee| fee.gas_limit)
+            .unwrap_or(0)
+    }
+}
+
+/// Returns the hash of a transaction, that is the SHA-256 of its raw bytes.
+pub fn hash(tx: &[u8]) -> Hash {
+    Hash::new(Sha256::digest(tx).into())
+}
+
+/// Run the checks of a raw transaction that do not require executing its messages, as
+/// Tendermint's `CheckTx` would.
+pub fn check_tx<S: Storage>(node: &SharedNode<S>, tx: &[u8]) -> TxResult {
+    let _txs = node.lock_txs();
+    run_tx(node, tx, Mode::Check)
+}
+
+/// Deliver a raw transaction, its state changes are discarded if the delivery fails.
+pub fn deliver_tx<S: Storage>(node: &SharedNode<S>, tx: &[u8]) -> TxResult {
+    let _txs = node.lock_txs();
+    run_tx(node, tx, Mode::Deliver)
+}
+
+/// Simulate the delivery of a raw transaction, none of its state changes are kept.
+pub fn simulate_tx<S: Storage>(node: &SharedNode<S>, tx: &[u8]) -> TxResult {
+    let _txs = node.lock_txs();
+    run_tx(node, tx, Mode::Simulate)
+}
+
+/// Broadcast a transaction and commit it in a new block, as Tendermint's `broadcast_tx_commit`.
+///
+/// Transactions failing the checks are not included in a block, in which case the returned height
+/// is 0 and the deliver result is empty. Transactions are rejected while no block can be produced,
+/// that is while the chain is halted or stalled.
+pub fn broadcast_tx_commit<S: Storage>(
+    node: &SharedNode<S>,
+    tx: Transaction,
+) -> Result<Committed, String> {
+    let data: Vec<u8> = tx.clone().into();
+    let hash = hash(&data);
+    // No other transaction nor block may come between the delivery and the commit
+    let _txs = node.lock_txs();
+    node.read().growth().check_growth()?;
+    let check_tx = run_tx(node, &data, Mode::Check);
+    if check_tx.code.is_err() {
+        return Ok(Committed {
+            hash,
+            check_tx,
+            deliver_tx: empty_result(),
+            height: 0_u32.into(),
+        });
+    }
+    let deliver_tx = run_tx(node, &data, Mode::Deliver);
+
+    // Grow chain with a block containing the transaction
+    let mut node = node.write();
+    if !node.grow_with_txs(vec![(hash, tx, deliver_tx.clone())]) {
+        // The chain was halted or stalled meanwhile, the transaction is left for the next block
+        return Err(String::from(
+            "the chain stopped producing blocks before the transaction was committed",
+        ));
+    }
+    let height = node
+        .chain()
+        .get_block(0)
+        .unwrap()
+        .signed_header
+        .header
+        .height;
+
+    Ok(Committed {
+        hash,
+        check_tx,
+        deliver_tx,
+        height,
+    })
+}
+
+/// Returns an empty result, used for the phases a transaction did not reach.
+pub fn empty_result() -> TxResult {
+    TxResult {
+        code: Code::Ok,
+        data: None,
+        log: "".into(),
+        codespace: Codespace::default(),
+        gas_used: 0.into(),
+        gas_wanted: 0.into(),
+        info: Info::default(),
+        events: vec![],
+    }
+}
+
+/// Run a transaction, the caller must hold the transaction lock.
+fn run_tx<S: Storage>(node: &SharedNode<S>, tx: &[u8], mode: Mode) -> TxResult {
+    let decoded = match decode(tx) {
+        Ok(decoded) => decoded,
+        Err(log) => return error_result(CODE_TX_DECODE, log, 0, 0),
+    };
+    let gas_wanted = decoded.gas_limit();
+    let gas_config = node.read().gas_config().clone();
+    if mode != Mode::Simulate {
+        if let Err(log) = check_fee(&decoded, &gas_config) {
+            return error_result(CODE_INSUFFICIENT_FEE, log, gas_wanted, 0);
+        }
+    }
+
+    // Simulations are not bounded by the gas limit, so that they can be used to estimate it
+    let gas_limit = if mode == Mode::Simulate {
+        u64::MAX
+    } else {
+        gas_wanted
+    };
+    let ctx = Arc::new(TxContext::new(gas_limit));
+    ctx.consume_gas(gas_config.tx_size_cost_per_byte * tx.len() as u64, "txSize");
+    execute(node, ctx, decoded, mode, &gas_config, gas_wanted)
+}
+
+/// Authenticate the transaction and execute its messages.
+///
+/// All the state changes go to the branch of the transaction, which is only committed when
+/// delivering: first once the transaction is authenticated, so that sequences are incremented
+/// even if the messages fail, then once the messages are successfully executed. The branch of
+/// checked and simulated transactions is dropped.
+fn execute<S: Storage>(
+    node: &SharedNode<S>,
+    ctx: Arc<TxContext>,
+    decoded: DecodedTx,
+    mode: Mode,
+    gas_config: &GasConfig,
+    gas_wanted: u64,
+) -> TxResult {
+    // Signatures are not verified when simulating, and sequences are only incremented when the
+    // messages are executed.
+    let auth_result = accounts::authenticate(
+        &node.read(),
+        &ctx,
+        &decoded.raw,
+        &decoded.auth_info,
+        mode != Mode::Simulate,
+        mode != Mode::Check,
+    );
+    if let Err(e) = auth_result {
+        return error_result(e.code, e.log, gas_wanted, ctx.gas_meter().consumed());
+    }
+
+    let meter = ctx.gas_meter();
+    if let Some(location) = meter.out_of_gas() {
+        return out_of_gas_result(location, gas_wanted, meter.consumed());
+    }
+    if mode == Mode::Check {
+        return success_result(vec![], gas_wanted, meter.consumed());
+    }
+    if mode == Mode::Deliver {
+        ctx.branch().commit(node.read().chain().get_store());
+    }
+
+    let messages = decoded.body.messages;
+    ctx.consume_gas(gas_config.msg_cost * messages.len() as u64, "msg");
+    let result = deliver(&mut node.with_tx(Arc::clone(&ctx)), messages);
+    let meter = ctx.gas_meter();
+    let gas_used = meter.consumed();
+
+    let (success, tx_result) = match (result, meter.out_of_gas()) {
+        (_, Some(location)) => {
+            log!(Log::Abci, "Transaction ran out of gas in {}", location);
+            (false, out_of_gas_result(location, gas_wanted, gas_used))
+        }
+        (Err(e), None) => {
+            log!(Log::Abci, "deliver error: '{}'", e);
+            let log = format!("failed to execute message; message index: 0: {}", e);
+            (
+                false,
+                error_result(CODE_INTERNAL, log, gas_wanted, gas_used),
+            )
+        }
+        (Ok(ibc_events), None) => (
+            true,
+            success_result(to_abci_events(ibc_events), gas_wanted, gas_used),
+        ),
+    };
+    if mode == Mode::Deliver && success {
+        ctx.branch().commit(node.read().chain().get_store());
+    }
+    tx_result
+}
+
+/// Decode a raw transaction.
+fn decode(tx: &[u8]) -> Result<DecodedTx, String> {
+    let tx_raw = TxRaw::decode(tx).map_err(|e| format!("{}: tx parse error", e))?;
+    let body = TxBody::decode(&*tx_raw.body_bytes).map_err(|e| format!("{}: tx parse error", e))?;
+    let auth_info =
+        AuthInfo::decode(&*tx_raw.auth_info_bytes).map_err(|e| format!("{}: tx parse error", e))?;
+    Ok(DecodedTx {
+        body,
+        auth_info,
+        raw: tx_raw,
+    })
+}
+
+/// Check that the fee of a transaction covers its gas limit at the minimum gas price.
+fn check_fee(tx: &DecodedTx, config: &GasConfig) -> Result<(), String> {
+    let required = (tx.gas_limit() as f64 * config.min_gas_price).ceil() as u128;
+    if required == 0 {
+        return Ok(());
+    }
+    let paid: u128 = tx
+        .auth_info
+        .fee
+        .iter()
+        .flat_map(|fee| fee.amount.iter())
+        .filter(|coin| coin.denom == config.fee_denom)
+        .filter_map(|coin| coin.amount.parse::<u128>().ok())
+        .sum();
+    if paid < required {
+        Err(format!(
+            "insufficient fees; got: {}{} required: {}{}: insufficient fee",
+            paid, config.fee_denom, required, config.fee_denom
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Transform `IBCEvent` into `abci::Event`, only the `create_client` events are supported for
+/// now: the other IBC events are not reported in the transaction results.
+// TODO: This is a workaround for https://github.com/informalsystems/ibc-rs/issues/838
+fn to_abci_events(ibc_events: Vec<IbcEvent>) -> Vec<Event> {
+    ibc_events
+        .iter()
+        .filter_map(|e| match e {
+            IbcEvent::CreateClient(c) => Some(Event {
+                type_str: "create_client".to_string(),
+                attributes: vec![Tag {
+                    key: "client_id".parse().unwrap(),
+                    value: c.client_id().to_string().parse().unwrap(),
+                }],
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn success_result(events: Vec<Event>, gas_wanted: u64, gas_used: u64) -> TxResult {
+    TxResult {
+        code: Code::Ok,
+        data: None,
+        log: "Success".into(),
+        codespace: Codespace::default(),
+        gas_used: gas_used.into(),
+        gas_wanted: gas_wanted.into(),
+        info: Info::default(),
+        events,
+    }
+}
+
+fn out_of_gas_result(location: &str, gas_wanted: u64, gas_used: u64) -> TxResult {
+    let log = format!(
+        "out of gas in location: {}; gasWanted: {}, gasUsed: {}: out of gas",
+        location, gas_wanted, gas_used
+    );
+    error_result(CODE_OUT_OF_GAS, log, gas_wanted, gas_used)
+}
+
+fn error_result(code: u32, log: String, gas_wanted: u64, gas_used: u64) -> TxResult {
+    TxResult {
+        code: Code::Err(code),
+        data: None,
+        log: AbciLog::from(log.as_str()),
+        // `Codespace` has no public constructor, but can be deserialized from a string.
+        codespace: serde_json::from_value(serde_json::Value::String(SDK_CODESPACE.to_string()))
+            .unwrap(),
+        gas_used: gas_used.into(),
+        gas_wanted: gas_wanted.into(),
+        info: Info::default(),
+        events: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc_proto::cosmos::base::v1beta1::Coin;
+    use ibc_proto::cosmos::tx::v1beta1::{Fee, SignDoc, SignerInfo};
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::{Signature, SigningKey};
+    use prost_types::Any;
+
+    use super::*;
+    use crate::accounts::{Secp256k1PubKey, CODE_NO_SIGNATURES, SECP256K1_PUB_KEY_TYPE_URL};
+    use crate::config::Config;
+    use crate::node::Node;
+    use crate::store::{Location, Memory};
+
+    /// Build a transaction without messages, signed by the first account of the node.
+    fn encode_tx(node: &SharedNode<Memory>, sequence: u64, gas_limit: u64, fee: u64) -> Vec<u8> {
+        let key = SigningKey::from_bytes(&[7; 32]).unwrap();
+        let mut pub_key = Vec::new();
+        Secp256k1PubKey {
+            key: key.verify_key().to_bytes().to_vec(),
+        }
+        .encode(&mut pub_key)
+        .unwrap();
+        let auth_info = AuthInfo {
+            signer_infos: vec![SignerInfo {
+                public_key: Some(Any {
+                    type_url: SECP256K1_PUB_KEY_TYPE_URL.to_owned(),
+                    value: pub_key,
+                }),
+                mode_info: None,
+                sequence,
+            }],
+            fee: Some(Fee {
+                amount: vec![Coin {
+                    denom: "stake".to_string(),
+                    amount: fee.to_string(),
+                }],
+                gas_limit,
+                payer: String::new(),
+                granter: String::new(),
+            }),
+        };
+        let body = TxBody {
+            messages: vec![],
+            memo: String::new(),
+            timeout_height: 0,
+            extension_options: vec![],
+            non_critical_extension_options: vec![],
+        };
+        let mut body_bytes = Vec::new();
+        body.encode(&mut body_bytes).unwrap();
+        let mut auth_info_bytes = Vec::new();
+        auth_info.encode(&mut auth_info_bytes).unwrap();
+        let sign_doc = SignDoc {
+            body_bytes: body_bytes.clone(),
+            auth_info_bytes: auth_info_bytes.clone(),
+            chain_id: node.read().chain_id().to_string(),
+            account_number: 0,
+        };
+        let mut sign_bytes = Vec::new();
+        sign_doc.encode(&mut sign_bytes).unwrap();
+        let signature: Signature = key.sign(&sign_bytes);
+        let tx_raw = TxRaw {
+            body_bytes,
+            auth_info_bytes,
+            signatures: vec![signature.as_ref().to_vec()],
+        };
+        let mut buffer = Vec::new();
+        tx_raw.encode(&mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn hash_is_sha256() {
+        assert_eq!(
+            hash(&[]).to_string(),
+            "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855"
+        );
+        assert_ne!(hash(b"tx_1"), hash(b"tx_2"));
+    }
+
+    #[test]
+    fn gas() {
+        let mut config = Config::default();
+        config.gas.min_gas_price = 0.5;
+        let node = Node::new(&config).shared();
+
+        // The signature verification and the creation of the account are charged on top of the
+        // transaction size
+        let tx = encode_tx(&node, 0, 100_000, 50_000);
+        let result = deliver_tx(&node, &tx);
+        assert_eq!(result.code, Code::Ok);
+        assert_eq!(result.gas_wanted, 100_000.into());
+        assert!(
+            result.gas_used.value()
+                > config.gas.tx_size_cost_per_byte * tx.len() as u64
+                    + config.gas.sig_verify_cost_secp256k1
+        );
+
+        // Not enough gas to pay for the transaction size
+        let tx = encode_tx(&node, 1, 10, 5);
+        let result = deliver_tx(&node, &tx);
+        assert_eq!(result.code, Code::Err(CODE_OUT_OF_GAS));
+
+        // The sequence is not incremented by a transaction running out of gas
+        let tx = encode_tx(&node, 1, 100_000, 50_000);
+        let result = check_tx(&node, &tx);
+        assert_eq!(result.code, Code::Ok);
+
+        // Not enough fees for the gas limit
+        let tx = encode_tx(&node, 1, 100_000, 10);
+        let result = check_tx(&node, &tx);
+        assert_eq!(result.code, Code::Err(CODE_INSUFFICIENT_FEE));
+
+        let result = check_tx(&node, b"not a tx");
+        assert_eq!(result.code, Code::Err(CODE_TX_DECODE));
+    }
+
+    #[test]
+    fn simulate() {
+        let mut config = Config::default();
+        config.gas.min_gas_price = 0.5;
+        let node = Node::new(&config).shared();
+
+        // Simulations are neither bounded by the gas limit nor subject to the fee checks
+        let tx = encode_tx(&node, 0, 10, 0);
+        let result = simulate_tx(&node, &tx);
+        assert_eq!(result.code, Code::Ok);
+        assert_eq!(result.gas_wanted, 10.into());
+        assert!(result.gas_used.value() > config.gas.tx_size_cost_per_byte * tx.len() as u64);
+
+        // None of the state changes of the simulation are kept: the account of the signer is not
+        // created and its sequence is still 0
+        let key = SigningKey::from_bytes(&[7; 32]).unwrap();
+        let address = accounts::address(&key.verify_key().to_bytes(), "cosmos");
+        let account = accounts::get_account(&node.read().store(), Location::Pending, &address);
+        assert_eq!(account, None);
+        let tx = encode_tx(&node, 0, 100_000, 50_000);
+        assert_eq!(deliver_tx(&node, &tx).code, Code::Ok);
+
+        // Nor do they affect the delivered state
+        let tx = encode_tx(&node, 1, 100_000, 50_000);
+        assert_eq!(simulate_tx(&node, &tx).code, Code::Ok);
+        assert_eq!(simulate_tx(&node, &tx).code, Code::Ok);
+        assert_eq!(deliver_tx(&node, &tx).code, Code::Ok);
+    }
+
+    #[test]
+    fn no_signatures() {
+        let node = Node::new(&Config::default()).shared();
+        let mut tx = TxRaw::decode(encode_tx(&node, 0, 100_000, 0).as_slice()).unwrap();
+        let mut auth_info = AuthInfo::decode(tx.auth_info_bytes.as_slice()).unwrap();
+        auth_info.signer_infos.clear();
+        tx.auth_info_bytes.clear();
+        auth_info.encode(&mut tx.auth_info_bytes).unwrap();
+        tx.signatures.clear();
+        let mut raw = Vec::new();
+        tx.encode(&mut raw).unwrap();
+
+        let result = check_tx(&node, &raw);
+        assert_eq!(result.code, Code::Err(CODE_NO_SIGNATURES));
+        assert_eq!(result.log.to_string(), "no signatures supplied");
+    }
+}
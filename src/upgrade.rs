@@ -0,0 +1,175 @@
+//! # Upgrade
+//!
+//! This module handles planned chain upgrades. The last block of the current revision is produced
+//! at the upgrade height, the next block starts a new revision: the revision number is
+//! incremented, the chain id changes (e.g. from `chain-A-1` to `chain-A-2`) and the revision height
+//! restarts from 1.
+//!
+//! As in the Cosmos SDK, the upgraded client and consensus states are committed under the upgrade
+//! path in the block preceding the upgrade height, so that the IBC clients of the chain can be
+//! upgraded.
+use ibc::ics02_client::client_consensus::AnyConsensusState;
+use ibc::ics07_tendermint::consensus_state::ConsensusState;
+use ibc::Height;
+use prost::Message;
+use prost_types::Any;
+
+use crate::init;
+use crate::logger::Log;
+use crate::node::Node;
+use crate::store::Storage;
+
+/// The prefix of the upgraded IBC states in the store.
+pub const UPGRADED_IBC_STATE: &str = "upgradedIBCState";
+
+/// A planned upgrade of the chain.
+#[derive(Clone, Debug)]
+pub struct UpgradePlan {
+    /// The height of the last block of the current revision.
+    pub height: u64,
+    /// The chain id of the next revision.
+    pub chain_id: String,
+}
+
+/// The revision number of the chain ids without revision suffix, as the heights of the chain
+/// have always been in revision 1.
+const DEFAULT_REVISION: u64 = 1;
+
+/// Returns the revision number of a chain id, which is its numerical suffix if the chain id is
+/// in the `{name}-{revision}` format, and 1 otherwise.
+pub fn revision_number(chain_id: &str) -> u64 {
+    split_chain_id(chain_id).1.unwrap_or(DEFAULT_REVISION)
+}
+
+/// Returns the chain id of the revision following the one of `chain_id`.
+pub fn next_chain_id(chain_id: &str) -> String {
+    let (name, revision) = split_chain_id(chain_id);
+    format!("{}-{}", name, revision.unwrap_or(DEFAULT_REVISION) + 1)
+}
+
+fn split_chain_id(chain_id: &str) -> (&str, Option<u64>) {
+    match chain_id.rfind('-') {
+        Some(idx) => match chain_id[idx + 1..].parse() {
+            Ok(revision) => (&chain_id[..idx], Some(revision)),
+            Err(_) => (chain_id, None),
+        },
+        None => (chain_id, None),
+    }
+}
+
+/// The path of the upgraded client state for an upgrade at `height`.
+pub fn upgraded_client_path(height: u64) -> String {
+    format!("{}/{}/upgradedClient", UPGRADED_IBC_STATE, height)
+}
+
+/// The path of the upgraded consensus state for an upgrade at `height`.
+pub fn upgraded_consensus_state_path(height: u64) -> String {
+    format!("{}/{}/upgradedConsState", UPGRADED_IBC_STATE, height)
+}
+
+/// Store the client state of the next revision, as well as the consensus state of the latest
+/// block, under the upgrade path.
+pub fn store_upgraded_states<S: Storage>(node: &Node<S>, plan: &UpgradePlan) {
+    let revision = revision_number(&plan.chain_id);
+    // The upgraded client must not be frozen, otherwise it could not be used
+    let client_state =
+        init::new_client_state(&plan.chain_id, Height::new(revision, 1), Height::new(0, 0));
+    let block = node
+        .chain()
+        .get_block(0)
+        .expect("The chain should always contain a block");
+    let consensus_state =
+        AnyConsensusState::Tendermint(ConsensusState::from(block.signed_header.header));
+
+    let store = node.store();
+    let client_path = upgraded_client_path(plan.height);
+    let consensus_state_path = upgraded_consensus_state_path(plan.height);
+    store.set(
+        client_path.clone().into_bytes(),
+        encode(client_state.into()),
+    );
+    store.set(
+        consensus_state_path.clone().into_bytes(),
+        encode(consensus_state.into()),
+    );
+    log!(
+        Log::Store,
+        "Storing upgraded states at {} and {}",
+        client_path,
+        consensus_state_path
+    );
+}
+
+fn encode(data: Any) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    data.encode(&mut buffer)
+        .expect("[Internal] Failed to encode upgraded state");
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc::ics02_client::client_state::{AnyClientState, ClientState};
+    use tendermint_proto::Protobuf;
+
+    use super::*;
+    use crate::config::{Config, Upgrade};
+    use crate::store::Location;
+
+    #[test]
+    fn chain_id() {
+        assert_eq!(revision_number("chain-A-1"), 1);
+        assert_eq!(revision_number("chain-A-0"), 0);
+        assert_eq!(revision_number("tendermock"), 1);
+        assert_eq!(revision_number("chain-A"), 1);
+        assert_eq!(next_chain_id("chain-A-1"), "chain-A-2");
+        assert_eq!(next_chain_id("tendermock"), "tendermock-2");
+    }
+
+    #[test]
+    fn default_revision() {
+        // Chain ids without revision suffix keep the heights in revision 1
+        let node = Node::new(&Config::default());
+        assert_eq!(node.chain_id().as_str(), "tendermock");
+        assert_eq!(node.chain().get_height(), Height::new(1, 1));
+    }
+
+    #[test]
+    fn upgrade() {
+        let mut config = Config::default();
+        config.chain_id = String::from("chain-A-1");
+        config.upgrade = Some(Upgrade {
+            height: 4,
+            chain_id: None,
+        });
+        let node = Node::new(&config);
+        let chain = node.chain();
+        assert_eq!(chain.get_height(), Height::new(1, 1));
+
+        let client_path = upgraded_client_path(4);
+        let consensus_state_path = upgraded_consensus_state_path(4);
+        node.grow();
+        node.grow();
+        assert_eq!(chain.get_height(), Height::new(1, 3));
+        let store = node.store();
+        let client_state = store
+            .get(Location::LatestStable, client_path.as_bytes())
+            .unwrap();
+
+        // The upgraded client is not frozen, and starts at the first height of the new revision
+        let client_state = AnyClientState::decode(client_state.as_slice()).unwrap();
+        assert!(!client_state.is_frozen());
+        assert_eq!(client_state.latest_height(), Height::new(2, 1));
+        assert!(store
+            .get(Location::LatestStable, consensus_state_path.as_bytes())
+            .is_some());
+
+        node.grow();
+        assert_eq!(chain.get_height(), Height::new(1, 4));
+        assert_eq!(node.chain_id().as_str(), "chain-A-1");
+        node.grow();
+        assert_eq!(chain.get_height(), Height::new(2, 1));
+        assert_eq!(node.chain_id().as_str(), "chain-A-2");
+        assert_eq!(node.growth().height(), 5);
+    }
+}
@@ -15,6 +15,7 @@ const JRPC_QUERIES: &[&str] = &[
     "abci_info.json",
     "abci_query.json",
     "block.json",
+    "blockchain.json",
     "commit.json",
     "genesis.json",
     "status.json",